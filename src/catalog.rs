@@ -0,0 +1,723 @@
+use crate::checksum::{checksum_file, Checksum};
+use crate::exif::Exif;
+use crate::{generate_camera, Photo};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use pickledb::{PickleDb, SerializationMethod};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// The catalog, kept at the root of the library.
+pub const CATALOG_FILE: &str = "catalog.db";
+
+/// The PickleDb `checksum -> output_filename` map the catalog replaced.
+pub const LEGACY_DB_FILE: &str = "photohash.db";
+
+/// How many backups of the catalog are kept, as `catalog.db.1` (the most
+/// recent) to `catalog.db.5`.
+const BACKUPS: usize = 5;
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have been applied to a catalog.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE batches (
+        id INTEGER PRIMARY KEY,
+        started_at TEXT NOT NULL,
+        sources TEXT NOT NULL
+    );
+    CREATE TABLE photos (
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        capture_time TEXT,
+        camera TEXT,
+        album TEXT,
+        gps_latitude TEXT,
+        gps_longitude TEXT,
+        batch INTEGER REFERENCES batches(id),
+        PRIMARY KEY (hash, size)
+    );
+    CREATE INDEX photos_path ON photos(path);
+",
+    "
+    ALTER TABLE photos ADD COLUMN last_verified TEXT;
+",
+    // `hash` and `size` described the source file, which is what duplicates
+    // are detected by. The library copy differs once tags are written into
+    // it, so it gets its own fingerprint.
+    "
+    ALTER TABLE photos RENAME COLUMN hash TO source_hash;
+    ALTER TABLE photos RENAME COLUMN size TO source_size;
+    ALTER TABLE photos ADD COLUMN file_hash TEXT;
+    ALTER TABLE photos ADD COLUMN file_size INTEGER;
+",
+    // Entries migrated from adler32 keys in `photohash.db` only know their
+    // source by that key.
+    "
+    ALTER TABLE photos ADD COLUMN legacy_key INTEGER;
+    CREATE INDEX photos_legacy_key ON photos(legacy_key);
+",
+];
+
+/// What the catalog records about a file in the library.
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    /// Relative to the library root.
+    pub path: String,
+    /// Fingerprint of the file as it was imported, before any tags were
+    /// written. Files are duplicates when these match.
+    pub source: Checksum,
+    /// Fingerprint of the library file itself, if known.
+    pub file: Option<Checksum>,
+    /// The source's adler32 checksum, for entries migrated from a
+    /// `photohash.db` keyed by it. Their `source` is only a stand-in.
+    pub legacy_key: Option<u32>,
+    pub capture_time: Option<String>,
+    pub camera: Option<String>,
+    pub album: Option<String>,
+    pub gps_latitude: Option<String>,
+    pub gps_longitude: Option<String>,
+}
+
+impl CatalogEntry {
+    pub fn new(path: String, source: Checksum, exif: &Exif) -> CatalogEntry {
+        CatalogEntry {
+            path,
+            source,
+            file: None,
+            legacy_key: None,
+            capture_time: exif.capture_time.map(|t| t.to_rfc3339()),
+            camera: generate_camera(exif),
+            album: exif.album.clone(),
+            gps_latitude: exif.gps_latitude.clone(),
+            gps_longitude: exif.gps_longitude.clone(),
+        }
+    }
+
+    /// The entry for `photo` once it has been imported as a file with
+    /// fingerprint `file`.
+    pub fn for_photo(photo: &Photo, file: Checksum) -> CatalogEntry {
+        CatalogEntry {
+            file: Some(file),
+            ..CatalogEntry::new(
+                photo.output_filename.clone(),
+                photo.checksum.clone(),
+                &photo.exif,
+            )
+        }
+    }
+}
+
+/// A catalog entry whose library file is due to be checked.
+#[derive(Debug, Clone)]
+pub struct DueFile {
+    /// Relative to the library root.
+    pub path: String,
+    /// The library file's recorded fingerprint. Entries from before these
+    /// were kept have none.
+    pub file: Option<Checksum>,
+    /// The expected size in bytes, for progress.
+    pub size: u64,
+}
+
+/// SQLite index of every file imported into a library, used to skip
+/// duplicates across runs.
+pub struct Catalog {
+    conn: Connection,
+    /// The import run new entries are attributed to.
+    batch: Option<i64>,
+    /// Whether any entry is known only by its adler32 key.
+    has_legacy_keys: bool,
+}
+
+impl Catalog {
+    /// Whether the library at `output_dir` has a catalog, in either format.
+    pub fn exists<P: AsRef<Path>>(output_dir: P) -> bool {
+        let output_dir = output_dir.as_ref();
+        output_dir.join(CATALOG_FILE).exists() || output_dir.join(LEGACY_DB_FILE).exists()
+    }
+
+    /// Opens the catalog of the library at `output_dir` and folds in any
+    /// `photohash.db` left by earlier versions. A missing catalog is created.
+    /// An unreadable one is an error, unless `rebuild` is set, in which case it
+    /// is set aside and a new one started.
+    pub fn open<P: AsRef<Path>>(output_dir: P, rebuild: bool) -> Result<Catalog> {
+        Catalog::open_with(output_dir.as_ref(), rebuild, false)
+    }
+
+    /// Like [`Catalog::open`], but first adds the catalog to its rotating
+    /// backups. For imports, the runs that add to it.
+    pub fn open_and_back_up<P: AsRef<Path>>(output_dir: P, rebuild: bool) -> Result<Catalog> {
+        Catalog::open_with(output_dir.as_ref(), rebuild, true)
+    }
+
+    fn open_with(output_dir: &Path, rebuild: bool, back_up: bool) -> Result<Catalog> {
+        std::fs::create_dir_all(output_dir)?;
+
+        let path = output_dir.join(CATALOG_FILE);
+        if path.exists() {
+            match check(&path) {
+                Ok(()) if back_up => rotate_backups(&path)?,
+                Ok(()) => {}
+                Err(e) if rebuild => set_aside(&path, e)?,
+                Err(e) => return Err(unreadable(&path, e)),
+            }
+        } else {
+            info!("No catalog at {}, starting a new one", path.display());
+        }
+
+        let mut conn = Connection::open(&path)?;
+        // Each write is its own transaction; WAL keeps those cheap while
+        // still surviving the process being killed.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrate_schema(&mut conn)?;
+
+        let legacy = output_dir.join(LEGACY_DB_FILE);
+        if legacy.exists() {
+            let tx = conn.transaction()?;
+            match import_legacy_db(&tx, output_dir) {
+                Ok(migrated) => {
+                    tx.commit()?;
+
+                    let mut backup = legacy.clone().into_os_string();
+                    backup.push(".migrated");
+                    std::fs::rename(&legacy, &backup)?;
+
+                    info!(
+                        "Migrated {} entries from {} to {}",
+                        migrated, LEGACY_DB_FILE, CATALOG_FILE
+                    );
+                }
+                Err(e) if rebuild => set_aside(&legacy, e)?,
+                Err(e) => return Err(unreadable(&legacy, e)),
+            }
+        }
+
+        Catalog::new(conn)
+    }
+
+    /// Opens the catalog without ever writing to it, for commands that must
+    /// leave the library untouched. A library without a catalog yet gets an
+    /// in-memory one, filled from `photohash.db` if there is one. With
+    /// `rebuild`, unreadable databases are ignored rather than an error.
+    pub fn open_read_only<P: AsRef<Path>>(output_dir: P, rebuild: bool) -> Result<Catalog> {
+        let output_dir = output_dir.as_ref();
+        let path = output_dir.join(CATALOG_FILE);
+
+        if path.exists() {
+            match check(&path) {
+                Ok(()) => return Catalog::new(open_untouched(&path)?),
+                Err(e) if rebuild => warn!("Ignoring {}: {:#}", path.display(), e),
+                Err(e) => return Err(unreadable(&path, e)),
+            }
+        }
+
+        let mut conn = Connection::open_in_memory()?;
+        migrate_schema(&mut conn)?;
+
+        let legacy = output_dir.join(LEGACY_DB_FILE);
+        if legacy.exists() {
+            let tx = conn.transaction()?;
+            match import_legacy_db(&tx, output_dir) {
+                Ok(_) => tx.commit()?,
+                Err(e) if rebuild => warn!("Ignoring {}: {:#}", legacy.display(), e),
+                Err(e) => return Err(unreadable(&legacy, e)),
+            }
+        }
+
+        Catalog::new(conn)
+    }
+
+    fn new(conn: Connection) -> Result<Catalog> {
+        let has_legacy_keys = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM photos WHERE legacy_key IS NOT NULL)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(Catalog {
+            conn,
+            batch: None,
+            has_legacy_keys,
+        })
+    }
+
+    /// Closes the catalog. Closing the last connection folds the write-ahead
+    /// log back into `catalog.db` and removes the `-wal` and `-shm` files.
+    pub fn close(self) -> Result<()> {
+        self.conn.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+
+    /// Starts a new import batch; entries recorded from now on belong to it.
+    pub fn begin_batch(&mut self, sources: &[PathBuf]) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO batches (started_at, sources) VALUES (?1, ?2)",
+            params![
+                Local::now().to_rfc3339(),
+                sources
+                    .iter()
+                    .map(|s| s.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ],
+        )?;
+        self.batch = Some(self.conn.last_insert_rowid());
+        Ok(())
+    }
+
+    /// The library path of the file imported from a source with `checksum`,
+    /// if there is one.
+    pub fn find(&self, checksum: &Checksum) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT path FROM photos WHERE source_hash = ?1 AND source_size = ?2",
+                params![checksum.blake3, checksum.size],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// The source the library file at `path` was imported from, if the
+    /// catalog has it.
+    pub fn source_of(&self, path: &str) -> Result<Option<Checksum>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT source_hash, source_size FROM photos WHERE path = ?1",
+                [path],
+                |row| {
+                    Ok(Checksum {
+                        blake3: row.get(0)?,
+                        size: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Whether some entries can only be found with [`Catalog::find_legacy`].
+    pub fn has_legacy_keys(&self) -> bool {
+        self.has_legacy_keys
+    }
+
+    /// The library path of the file imported from a source with the adler32
+    /// checksum `key`, for entries migrated from `photohash.db`.
+    pub fn find_legacy(&self, key: u32) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT path FROM photos WHERE legacy_key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Records a file in the library. An existing entry for the same source
+    /// is left as it is.
+    pub fn record(&self, entry: &CatalogEntry) -> Result<()> {
+        insert(&self.conn, entry, self.batch)
+    }
+
+    /// Removes the entry for the library file at `path`.
+    pub fn forget(&self, path: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM photos WHERE path = ?1", [path])?;
+        Ok(())
+    }
+
+    /// The library path of every entry.
+    pub fn paths(&self) -> Result<HashSet<String>> {
+        let mut statement = self.conn.prepare("SELECT path FROM photos")?;
+        let paths = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+
+    /// Entries due for verification: those never verified, or last verified
+    /// before `verified_before` if given, otherwise all of them. With `sample`,
+    /// at most that many are picked at random.
+    pub fn due_for_verification(
+        &self,
+        verified_before: Option<DateTime<Utc>>,
+        sample: Option<usize>,
+    ) -> Result<Vec<DueFile>> {
+        let mut statement = self.conn.prepare(
+            "SELECT path, file_hash, file_size, source_size FROM photos
+             WHERE ?1 IS NULL OR last_verified IS NULL OR last_verified < ?1
+             ORDER BY CASE WHEN ?2 IS NULL THEN path ELSE random() END
+             LIMIT coalesce(?2, -1)",
+        )?;
+
+        let entries = statement
+            .query_map(
+                params![verified_before.map(timestamp), sample.map(|n| n as i64)],
+                |row| {
+                    let file = match (row.get(1)?, row.get(2)?) {
+                        (Some(blake3), Some(size)) => Some(Checksum { blake3, size }),
+                        _ => None,
+                    };
+                    Ok(DueFile {
+                        path: row.get(0)?,
+                        size: file.as_ref().map_or(row.get(3), |f| Ok(f.size))?,
+                        file,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
+    /// Records that the files at the given paths were found with the given
+    /// checksums at `when`.
+    pub fn mark_verified(
+        &mut self,
+        files: &[(&str, &Checksum)],
+        when: DateTime<Utc>,
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for (path, checksum) in files {
+            tx.execute(
+                "UPDATE photos SET last_verified = ?1, file_hash = ?2, file_size = ?3
+                 WHERE path = ?4",
+                params![timestamp(when), checksum.blake3, checksum.size, path],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Makes the catalog list exactly `entries`, in one transaction. An entry
+    /// for a file the catalog already has, at the same path or moved
+    /// elsewhere unchanged, keeps the source it was imported from, so that
+    /// re-importing that source is still caught as a duplicate. It also keeps
+    /// its import batch and, if the file is unchanged, when it was last
+    /// verified. Other entries stand as their own source. Returns the number
+    /// of entries dropped because no file in `entries` accounts for them.
+    pub fn replace_all(&mut self, entries: &[CatalogEntry]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+
+        let known = tx
+            .prepare(
+                "SELECT path, source_hash, source_size, file_hash, file_size, batch, last_verified,
+                     legacy_key
+                 FROM photos",
+            )?
+            .query_map([], |row| {
+                Ok(KnownEntry {
+                    path: row.get(0)?,
+                    source: Checksum {
+                        blake3: row.get(1)?,
+                        size: row.get(2)?,
+                    },
+                    file: match (row.get(3)?, row.get(4)?) {
+                        (Some(blake3), Some(size)) => Some(Checksum { blake3, size }),
+                        _ => None,
+                    },
+                    batch: row.get(5)?,
+                    last_verified: row.get(6)?,
+                    legacy_key: row.get(7)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Match by path first, so that a file which stayed put isn't claimed
+        // by an identical copy elsewhere.
+        let by_path: HashMap<&str, usize> = known
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.path.as_str(), i))
+            .collect();
+        let mut matches: Vec<Option<usize>> = entries
+            .iter()
+            .map(|entry| by_path.get(entry.path.as_str()).copied())
+            .collect();
+        let mut claimed: HashSet<usize> = matches.iter().flatten().copied().collect();
+
+        let mut by_file: HashMap<&Checksum, usize> = HashMap::new();
+        for (i, k) in known.iter().enumerate() {
+            if let Some(file) = &k.file {
+                by_file.entry(file).or_insert(i);
+            }
+        }
+        for (entry, found) in entries.iter().zip(matches.iter_mut()) {
+            if found.is_none() {
+                *found = entry
+                    .file
+                    .as_ref()
+                    .and_then(|file| by_file.get(file).copied())
+                    .filter(|i| claimed.insert(*i));
+            }
+        }
+
+        tx.execute("DELETE FROM photos", [])?;
+        for (entry, found) in entries.iter().zip(&matches) {
+            match found.map(|i| &known[i]) {
+                Some(known) => {
+                    let entry = CatalogEntry {
+                        source: known.source.clone(),
+                        legacy_key: known.legacy_key,
+                        ..entry.clone()
+                    };
+                    insert(&tx, &entry, known.batch)?;
+                    if known.file.is_some() && known.file == entry.file {
+                        tx.execute(
+                            "UPDATE photos SET last_verified = ?1 WHERE path = ?2",
+                            params![known.last_verified, entry.path],
+                        )?;
+                    }
+                }
+                None => insert(&tx, entry, None)?,
+            }
+        }
+        tx.commit()?;
+
+        Ok(known.len() - claimed.len())
+    }
+}
+
+/// An entry as the catalog had it before a rebuild.
+struct KnownEntry {
+    path: String,
+    source: Checksum,
+    file: Option<Checksum>,
+    batch: Option<i64>,
+    last_verified: Option<String>,
+    legacy_key: Option<u32>,
+}
+
+/// Formats a time for the catalog. Always UTC and to the second, so that
+/// times compare correctly as text.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Adds `entry`, unless there already is one for the same source.
+fn insert(conn: &Connection, entry: &CatalogEntry, batch: Option<i64>) -> Result<()> {
+    conn.execute(
+        "INSERT INTO photos (source_hash, source_size, path, file_hash, file_size,
+             capture_time, camera, album, gps_latitude, gps_longitude, batch, legacy_key)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+         ON CONFLICT (source_hash, source_size) DO NOTHING",
+        params![
+            entry.source.blake3,
+            entry.source.size,
+            entry.path,
+            entry.file.as_ref().map(|f| &f.blake3),
+            entry.file.as_ref().map(|f| f.size),
+            entry.capture_time,
+            entry.camera,
+            entry.album,
+            entry.gps_latitude,
+            entry.gps_longitude,
+            batch,
+            entry.legacy_key
+        ],
+    )?;
+    Ok(())
+}
+
+/// Makes sure the file at `path` is an intact catalog. SQLite treats an empty
+/// file as an empty database, so a catalog without a schema counts as
+/// damaged too.
+fn check(path: &Path) -> Result<()> {
+    let conn = open_untouched(path)?;
+
+    let result: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if result != "ok" {
+        return Err(anyhow!("Integrity check failed: {}", result));
+    }
+
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version == 0 {
+        return Err(anyhow!("Not a photobot catalog"));
+    }
+
+    Ok(())
+}
+
+/// Opens the database at `path` for reading without creating any file next to
+/// it. Even a read-only connection to a WAL database sets up `-wal` and `-shm`
+/// files, so a cleanly closed catalog is opened as immutable. One left with
+/// its write-ahead log by an interrupted run is read together with that log,
+/// whose files are already there.
+fn open_untouched(path: &Path) -> Result<Connection> {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    if Path::new(&wal).exists() {
+        return Ok(Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?);
+    }
+
+    let uri = format!(
+        "file:{}?immutable=1",
+        path.to_string_lossy()
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23")
+    );
+    Ok(Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )?)
+}
+
+fn unreadable(path: &Path, error: anyhow::Error) -> anyhow::Error {
+    error.context(format!(
+        "{} is unreadable. Restore it from a backup, or rerun with --rebuild-db to set it aside and start an empty catalog",
+        path.display()
+    ))
+}
+
+/// Moves a damaged database, and SQLite's journal files with it, out of the
+/// way so that it can be inspected or recovered later.
+fn set_aside(path: &Path, error: anyhow::Error) -> Result<()> {
+    let suffix = format!(".corrupt-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let with_suffix = |path: &Path, suffix: &str| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+
+    let aside = with_suffix(path, &suffix);
+    std::fs::rename(path, &aside)?;
+
+    for journal in ["-wal", "-shm"] {
+        let journal_path = with_suffix(path, journal);
+        if journal_path.exists() {
+            std::fs::rename(&journal_path, with_suffix(&aside, journal))?;
+        }
+    }
+
+    warn!(
+        "{} is unreadable ({:#}); moved it to {} and starting over",
+        path.display(),
+        error,
+        aside.display()
+    );
+
+    Ok(())
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Shifts `catalog.db.1` through `catalog.db.4` up by one, dropping the
+/// oldest, and snapshots the catalog as the new `catalog.db.1`.
+fn rotate_backups(path: &Path) -> Result<()> {
+    let oldest = backup_path(path, BACKUPS);
+    if oldest.exists() {
+        std::fs::remove_file(&oldest)?;
+    }
+
+    for n in (1..BACKUPS).rev() {
+        let backup = backup_path(path, n);
+        if backup.exists() {
+            std::fs::rename(&backup, backup_path(path, n + 1))?;
+        }
+    }
+
+    // Unlike a file copy, this includes changes still in the WAL.
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        .execute("VACUUM INTO ?1", [backup_path(path, 1).to_string_lossy()])?;
+
+    Ok(())
+}
+
+fn migrate_schema(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "{} was written by a newer version of photobot (schema {}, expected at most {})",
+            CATALOG_FILE,
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    let tx = conn.transaction()?;
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Databases written before the switch to BLAKE3 are keyed by the adler32
+/// checksum, which is always a plain decimal number.
+fn parse_legacy_key(key: &str) -> Option<u32> {
+    key.parse().ok()
+}
+
+/// Parses a `blake3:<hex>:<size>` key as written by [`Checksum`]'s `Display`.
+fn parse_checksum_key(key: &str) -> Option<Checksum> {
+    let (blake3, size) = key.strip_prefix("blake3:")?.split_once(':')?;
+
+    Some(Checksum {
+        blake3: blake3.to_string(),
+        size: size.parse().ok()?,
+    })
+}
+
+/// Copies every entry of `photohash.db` into the catalog. Files recorded
+/// under a legacy adler32 key keep it to be found by, as their library files
+/// no longer hash like their sources did; those that no longer exist are
+/// dropped. Returns the number of entries copied.
+fn import_legacy_db(tx: &Transaction, output_dir: &Path) -> Result<usize> {
+    let path = output_dir.join(LEGACY_DB_FILE);
+    let db = PickleDb::load_read_only(&path, SerializationMethod::Json)
+        .map_err(|e| anyhow!(e.to_string()))
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+
+    let mut migrated = 0;
+
+    for key in db.get_all() {
+        let Some(output_filename) = db.get::<String>(&key) else {
+            continue;
+        };
+
+        let entry = if let Some(legacy_key) = parse_legacy_key(&key) {
+            // The library file stands in for the unknown source.
+            match checksum_file(output_dir.join(&output_filename)) {
+                Ok(checksum) => CatalogEntry {
+                    file: Some(checksum.clone()),
+                    legacy_key: Some(legacy_key),
+                    ..CatalogEntry::new(output_filename, checksum, &Exif::default())
+                },
+                Err(e)
+                    if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                        == Some(ErrorKind::NotFound) =>
+                {
+                    warn!(path = %output_filename, "Dropping entry for missing file");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        } else if let Some(checksum) = parse_checksum_key(&key) {
+            CatalogEntry::new(output_filename, checksum, &Exif::default())
+        } else {
+            warn!(key = %key, "Dropping entry with unrecognized key");
+            continue;
+        };
+
+        insert(tx, &entry, None)?;
+        migrated += 1;
+    }
+
+    Ok(migrated)
+}
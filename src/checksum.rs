@@ -0,0 +1,71 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+/// Content fingerprint of a file: its BLAKE3 digest together with its size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    pub blake3: String,
+    pub size: u64,
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "blake3:{}:{}", self.blake3, self.size)
+    }
+}
+
+pub fn checksum_file<P: AsRef<Path>>(path: P) -> Result<Checksum> {
+    Ok(hash_file(path, None)?.0)
+}
+
+/// Like [`checksum_file`], and also computes the adler32 checksum
+/// `photohash.db` was keyed by before BLAKE3, in the same read.
+pub fn checksum_file_with_legacy_key<P: AsRef<Path>>(path: P) -> Result<(Checksum, u32)> {
+    let (checksum, key) = hash_file(path, Some(adler32::RollingAdler32::new()))?;
+    Ok((checksum, key.unwrap_or_default()))
+}
+
+fn hash_file<P: AsRef<Path>>(
+    path: P,
+    adler32: Option<adler32::RollingAdler32>,
+) -> Result<(Checksum, Option<u32>)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut hashers = Hashers {
+        blake3: blake3::Hasher::new(),
+        adler32,
+    };
+
+    let size = io::copy(&mut file, &mut hashers)?;
+
+    Ok((
+        Checksum {
+            blake3: hashers.blake3.finalize().to_hex().to_string(),
+            size,
+        },
+        hashers.adler32.map(|a| a.hash()),
+    ))
+}
+
+/// Feeds what is written to each hash at once.
+struct Hashers {
+    blake3: blake3::Hasher,
+    adler32: Option<adler32::RollingAdler32>,
+}
+
+impl Write for Hashers {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.blake3.update(buf);
+        if let Some(adler32) = &mut self.adler32 {
+            adler32.update_buffer(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use crate::exif::Exif;
+use crate::generate_camera;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDateTime};
+use serde::Deserialize;
+
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// A clock correction as written in the config file. Either `offset` or one
+/// or two `references` must be given.
+///
+/// Corrections fix what the camera's clock read. Which zone that corrected
+/// reading is in is a separate matter, settled afterwards by `--camera-tz`,
+/// `camera_offsets` and the rest of [`crate::timezone::TimeZones::resolve`].
+/// A clock set to the wrong zone belongs in `camera_offsets`, not here, or it
+/// would be shifted twice.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ClockCorrectionConfig {
+    /// Make and model as rendered by `{camera}`, e.g. `Canon EOS R5`.
+    pub camera: String,
+    /// Restricts the correction to the body with this serial number.
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// Amount the camera clock was behind, e.g. `+01:07` or `-00:00:30`.
+    #[serde(default)]
+    pub offset: Option<String>,
+    /// Camera and true times of reference shots, such as a photo of a phone
+    /// clock. Two references taken apart also correct for drift.
+    #[serde(default)]
+    pub references: Vec<ReferencePoint>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReferencePoint {
+    /// The time the camera recorded, e.g. `2023:06:01 10:00:00`.
+    pub camera_time: String,
+    /// The time it actually was.
+    pub actual_time: String,
+}
+
+/// How far off a camera clock was.
+#[derive(Debug, Clone)]
+enum Adjustment {
+    /// Off by the same amount throughout.
+    Offset(Duration),
+    /// Off by `offset` at `reference`, gaining `rate` seconds for every second
+    /// since.
+    Drift {
+        reference: NaiveDateTime,
+        offset: Duration,
+        rate: f64,
+    },
+}
+
+/// Maps the readings of one camera's clock to the true time.
+#[derive(Debug, Clone)]
+struct ClockCorrection {
+    camera: String,
+    serial: Option<String>,
+    adjustment: Adjustment,
+}
+
+impl ClockCorrection {
+    fn parse(config: &ClockCorrectionConfig) -> Result<ClockCorrection> {
+        let references = config
+            .references
+            .iter()
+            .map(|r| Ok((parse_date(&r.camera_time)?, parse_date(&r.actual_time)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let adjustment = match (&config.offset, references.as_slice()) {
+            (Some(offset), []) => Adjustment::Offset(parse_duration(offset)?),
+            (None, [(camera, actual)]) => Adjustment::Offset(*actual - *camera),
+            (None, [(camera1, actual1), (camera2, actual2)]) if camera1 != camera2 => {
+                let offset1 = *actual1 - *camera1;
+                let offset2 = *actual2 - *camera2;
+                Adjustment::Drift {
+                    reference: *camera1,
+                    offset: offset1,
+                    rate: (offset2 - offset1).num_milliseconds() as f64
+                        / (*camera2 - *camera1).num_milliseconds() as f64,
+                }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Clock correction for '{}' needs either an offset or one or two references at different times",
+                    config.camera
+                ))
+            }
+        };
+
+        Ok(ClockCorrection {
+            camera: config.camera.clone(),
+            serial: config.serial.clone(),
+            adjustment,
+        })
+    }
+
+    fn apply(&self, date: NaiveDateTime) -> NaiveDateTime {
+        match self.adjustment {
+            Adjustment::Offset(offset) => date + offset,
+            Adjustment::Drift {
+                reference,
+                offset,
+                rate,
+            } => {
+                let drift = (date - reference).num_milliseconds() as f64 * rate;
+                date + offset + Duration::milliseconds(drift.round() as i64)
+            }
+        }
+    }
+}
+
+/// Parses a date in the EXIF `YYYY:MM:DD HH:MM:SS` layout.
+pub fn parse_date(s: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s.trim(), EXIF_DATE_FORMAT)
+        .map_err(|_| anyhow!("Invalid date '{}': expected e.g. 2023:06:01 10:00:00", s))
+}
+
+/// Parses a signed duration such as `+01:07`, `-00:00:30` or `2:00:00`.
+fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid clock offset '{}': expected e.g. +01:07:00", s);
+    let s = s.trim();
+
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let parts = rest
+        .split(':')
+        .map(|p| p.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let seconds = match parts.as_slice() {
+        [h, m] if *m < 60 => h * 3600 + m * 60,
+        [h, m, s] if *m < 60 && *s < 60 => h * 3600 + m * 60 + s,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Duration::seconds(sign * seconds))
+}
+
+/// The clock corrections from the config file.
+#[derive(Debug, Clone, Default)]
+pub struct ClockCorrections(Vec<ClockCorrection>);
+
+impl ClockCorrections {
+    pub fn parse(configs: &[ClockCorrectionConfig]) -> Result<ClockCorrections> {
+        Ok(ClockCorrections(
+            configs
+                .iter()
+                .map(ClockCorrection::parse)
+                .collect::<Result<_>>()?,
+        ))
+    }
+
+    /// Corrects every date recorded by the camera's clock. A correction for
+    /// the camera's serial number takes precedence over one for the model as a
+    /// whole. Returns whether a correction applied.
+    pub fn correct(&self, exif: &mut Exif) -> bool {
+        let Some(camera) = generate_camera(exif) else {
+            return false;
+        };
+
+        let matches_camera = |c: &&ClockCorrection| c.camera == camera;
+        let Some(correction) = self
+            .0
+            .iter()
+            .filter(matches_camera)
+            .find(|c| c.serial.is_some() && c.serial == exif.serial_number)
+            .or_else(|| {
+                self.0
+                    .iter()
+                    .filter(matches_camera)
+                    .find(|c| c.serial.is_none())
+            })
+        else {
+            return false;
+        };
+
+        for date in [
+            &mut exif.date_time_original,
+            &mut exif.create_date,
+            &mut exif.quicktime_create_date,
+            &mut exif.quicktime_media_create_date,
+        ] {
+            *date = date.map(|d| correction.apply(d));
+        }
+
+        true
+    }
+}
+
+/// Formats `date` the way it is written back into EXIF.
+pub fn format_exif_date(date: NaiveDateTime) -> String {
+    date.format(EXIF_DATE_FORMAT).to_string()
+}
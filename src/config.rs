@@ -0,0 +1,40 @@
+use crate::clock::ClockCorrectionConfig;
+use crate::datesource::DateSource;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Settings read from the JSON file passed with `--config`. Command line
+/// flags take precedence over anything set here.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Output path template, see [`crate::template::Template`].
+    pub template: Option<String>,
+    /// File extensions to import, without the leading dot.
+    pub extensions: Option<Vec<String>>,
+    /// Video file extensions to import, without the leading dot.
+    pub video_extensions: Option<Vec<String>>,
+    /// UTC offsets camera clocks were set to, e.g. `{"Canon EOS R5": "+09:00"}`,
+    /// keyed by make and model as rendered by `{camera}`.
+    pub camera_offsets: Option<HashMap<String, String>>,
+    /// Zone capture times are shown in: `local` or an offset such as `+02:00`.
+    pub display_tz: Option<String>,
+    /// Where capture dates may come from, in order of preference.
+    pub date_sources: Option<Vec<DateSource>>,
+    /// Corrections for cameras whose clocks were set wrong or drifted.
+    pub clock_corrections: Option<Vec<ClockCorrectionConfig>>,
+}
+
+pub fn load_config(path: Option<&Path>) -> Result<Config> {
+    match path {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Unable to read config {}: {}", path.display(), e))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))
+        }
+        None => Ok(Config::default()),
+    }
+}
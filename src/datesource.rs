@@ -0,0 +1,168 @@
+use crate::exif::Exif;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where a photo's capture date can come from, tried in the order given by
+/// `--date-sources`.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// EXIF DateTimeOriginal or CreateDate, or the QuickTime creation date
+    Exif,
+    /// XMP DateCreated or CreateDate
+    Xmp,
+    /// IPTC DateCreated and TimeCreated
+    Iptc,
+    /// The file name, e.g. IMG_20230104_101500.jpg
+    Filename,
+    /// The name of the containing folder, e.g. "2023-01-04 Birthday"
+    Folder,
+    /// The file's modification time
+    Mtime,
+}
+
+impl DateSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateSource::Exif => "exif",
+            DateSource::Xmp => "xmp",
+            DateSource::Iptc => "iptc",
+            DateSource::Filename => "filename",
+            DateSource::Folder => "folder",
+            DateSource::Mtime => "mtime",
+        }
+    }
+}
+
+/// Sources tried when none are configured. The modification time comes last,
+/// as copying a file usually resets it.
+pub const DEFAULT_DATE_SOURCES: &[DateSource] = &[
+    DateSource::Exif,
+    DateSource::Xmp,
+    DateSource::Iptc,
+    DateSource::Filename,
+    DateSource::Folder,
+    DateSource::Mtime,
+];
+
+/// Date and time in names such as `Screenshot 2023-01-04 at 10.15.00 AM` or
+/// `2023-01-04_10-15-00`.
+static SEPARATED_DATE_TIME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(\d{4})-(\d{2})-(\d{2})[ _T]+(?:at )?(\d{1,2})[.:_-](\d{2})[.:_-](\d{2})(?:\s*([AP]M))?")
+        .unwrap()
+});
+
+/// Date and time in names such as `IMG_20230104_101500` or
+/// `PXL_20230104_101500123`.
+static COMPACT_DATE_TIME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|\D)(\d{4})(\d{2})(\d{2})[_-](\d{2})(\d{2})(\d{2})").unwrap());
+
+/// A date alone, as in WhatsApp's `IMG-20230104-WA0001` or `2023-01-04 Trip`.
+static DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|\D)(\d{4})[-_.]?(\d{2})[-_.]?(\d{2})(?:\D|$)").unwrap());
+
+fn number(captures: &Captures, i: usize) -> Option<u32> {
+    captures.get(i)?.as_str().parse().ok()
+}
+
+fn date_from_captures(captures: &Captures) -> Option<NaiveDate> {
+    let year = number(captures, 1)? as i32;
+    if !(1900..=2100).contains(&year) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, number(captures, 2)?, number(captures, 3)?)
+}
+
+fn date_time_from_captures(captures: &Captures) -> Option<NaiveDateTime> {
+    let mut hour = number(captures, 4)?;
+
+    match captures.get(7).map(|m| m.as_str().to_ascii_uppercase()) {
+        Some(pm) if pm == "PM" && hour < 12 => hour += 12,
+        Some(am) if am == "AM" && hour == 12 => hour = 0,
+        _ => {}
+    }
+
+    date_from_captures(captures)?.and_hms_opt(hour, number(captures, 5)?, number(captures, 6)?)
+}
+
+/// Finds a date in a file or folder name. Names with only a date are taken
+/// to be at midnight.
+pub fn date_from_name(name: &str) -> Option<NaiveDateTime> {
+    SEPARATED_DATE_TIME
+        .captures_iter(name)
+        .chain(COMPACT_DATE_TIME.captures_iter(name))
+        .find_map(|c| date_time_from_captures(&c))
+        .or_else(|| {
+            DATE.captures_iter(name)
+                .find_map(|c| date_from_captures(&c))
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Parses XMP and IPTC dates as printed by exiftool (`2023:01:04 10:15:00`,
+/// possibly with fractional seconds or an offset) or as stored in XMP
+/// (`2023-01-04T10:15:00`). A missing time is taken to be midnight.
+pub fn parse_metadata_date(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    let date = NaiveDate::parse_from_str(&s.get(..10)?.replace('-', ":"), "%Y:%m:%d").ok()?;
+    let time = match s.get(11..19) {
+        Some(time) => NaiveTime::parse_from_str(time, "%H:%M:%S").ok()?,
+        None => NaiveTime::from_hms_opt(0, 0, 0)?,
+    };
+
+    Some(date.and_time(time))
+}
+
+fn date_from_source(source: DateSource, exif: &Exif, path: &Path) -> Option<NaiveDateTime> {
+    match source {
+        // Embedded dates need their offsets worked out; see
+        // `choose_date_source`.
+        DateSource::Exif => None,
+        DateSource::Xmp => exif
+            .xmp_date_created
+            .as_deref()
+            .or(exif.xmp_create_date.as_deref())
+            .and_then(parse_metadata_date),
+        DateSource::Iptc => {
+            let date = exif.iptc_date_created.as_deref()?;
+            parse_metadata_date(&match exif.iptc_time_created.as_deref() {
+                Some(time) => format!("{} {}", date, time),
+                None => date.to_string(),
+            })
+        }
+        DateSource::Filename => date_from_name(&path.file_stem()?.to_string_lossy()),
+        DateSource::Folder => date_from_name(&path.parent()?.file_name()?.to_string_lossy()),
+        DateSource::Mtime => {
+            let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            Some(DateTime::<Local>::from(modified).naive_local())
+        }
+    }
+}
+
+/// Walks `sources` in order and settles on the first one that yields a date.
+/// Dates from anything other than the embedded EXIF and QuickTime dates are
+/// stored in `exif.fallback_date`. Returns the source used, if any; without
+/// one, the photo has no capture date, even if it has embedded dates.
+pub fn choose_date_source(
+    exif: &mut Exif,
+    path: &Path,
+    sources: &[DateSource],
+) -> Option<DateSource> {
+    for source in sources {
+        // Embedded dates stay where they are, for `TimeZones::resolve` to
+        // place in the right zone.
+        if *source == DateSource::Exif {
+            if exif.has_embedded_date() {
+                return Some(*source);
+            }
+        } else if let Some(date) = date_from_source(*source, exif, path) {
+            exif.fallback_date = Some(date);
+            return Some(*source);
+        }
+    }
+
+    None
+}
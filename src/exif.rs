@@ -1,9 +1,18 @@
-use anyhow::{anyhow, Result};
+mod exiftool;
+mod native;
+mod xmp;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use std::io::ErrorKind;
-use std::{path::Path, process::Command};
+use std::path::{Path, PathBuf};
+use tracing::trace;
 
+use crate::clock::format_exif_date;
+use crate::timezone::parse_offset;
 use crate::Photo;
+use anyhow::Result;
+use exiftool::{ExiftoolBackend, ExiftoolUnavailableError};
+use native::NativeBackend;
 
 mod exiftool_date_format {
     use chrono::naive::NaiveDateTime;
@@ -40,7 +49,64 @@ mod exiftool_date_format {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// QuickTime dates are frequently left as `0000:00:00 00:00:00` by cameras
+/// without a clock, so values that do not parse are treated as missing.
+mod exiftool_lenient_date_format {
+    use chrono::naive::NaiveDateTime;
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+    pub fn serialize<S>(date: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::exiftool_date_format::serialize(date, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: Option<String> = Option::deserialize(deserializer)?;
+        Ok(s.and_then(|s| NaiveDateTime::parse_from_str(&s, FORMAT).ok()))
+    }
+}
+
+mod exiftool_string_or_number {
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(serde_json::Number),
+    }
+
+    pub fn serialize<S>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(s) => serializer.serialize_str(s),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            Option::<StringOrNumber>::deserialize(deserializer)?.map(|v| match v {
+                StringOrNumber::String(s) => s,
+                StringOrNumber::Number(n) => n.to_string(),
+            }),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Exif {
     #[serde(rename = "EXIF:DateTimeOriginal")]
     #[serde(default)]
@@ -50,6 +116,34 @@ pub struct Exif {
     #[serde(rename = "EXIF:CreateDate")]
     #[serde(default)]
     pub create_date: Option<chrono::naive::NaiveDateTime>,
+    #[serde(rename = "EXIF:SubSecTimeOriginal")]
+    #[serde(default)]
+    #[serde(with = "exiftool_string_or_number")]
+    pub sub_sec_time_original: Option<String>,
+    /// UTC offset of `date_time_original`, e.g. `+02:00`.
+    #[serde(rename = "EXIF:OffsetTimeOriginal")]
+    #[serde(default)]
+    pub offset_time_original: Option<String>,
+    #[serde(rename = "QuickTime:CreateDate")]
+    #[serde(default)]
+    #[serde(with = "exiftool_lenient_date_format")]
+    pub quicktime_create_date: Option<chrono::naive::NaiveDateTime>,
+    #[serde(rename = "QuickTime:MediaCreateDate")]
+    #[serde(default)]
+    #[serde(with = "exiftool_lenient_date_format")]
+    pub quicktime_media_create_date: Option<chrono::naive::NaiveDateTime>,
+    #[serde(rename = "XMP:DateCreated")]
+    #[serde(default)]
+    pub xmp_date_created: Option<String>,
+    #[serde(rename = "XMP:CreateDate")]
+    #[serde(default)]
+    pub xmp_create_date: Option<String>,
+    #[serde(rename = "IPTC:DateCreated")]
+    #[serde(default)]
+    pub iptc_date_created: Option<String>,
+    #[serde(rename = "IPTC:TimeCreated")]
+    #[serde(default)]
+    pub iptc_time_created: Option<String>,
     #[serde(rename = "XMP:Album")]
     #[serde(default)]
     pub album: Option<String>,
@@ -62,59 +156,318 @@ pub struct Exif {
     #[serde(rename = "EXIF:Model")]
     #[serde(default)]
     pub model: Option<String>,
+    #[serde(rename = "EXIF:SerialNumber")]
+    #[serde(default)]
+    #[serde(with = "exiftool_string_or_number")]
+    pub serial_number: Option<String>,
+    #[serde(rename = "QuickTime:Make")]
+    #[serde(default)]
+    pub quicktime_make: Option<String>,
+    #[serde(rename = "QuickTime:Model")]
+    #[serde(default)]
+    pub quicktime_model: Option<String>,
+    #[serde(rename = "MakerNotes:ContentIdentifier")]
+    #[serde(default)]
+    pub makernotes_content_identifier: Option<String>,
+    #[serde(rename = "QuickTime:ContentIdentifier")]
+    #[serde(default)]
+    pub quicktime_content_identifier: Option<String>,
     #[serde(rename = "EXIF:GPSLatitude")]
     #[serde(default)]
     pub gps_latitude: Option<String>,
     #[serde(rename = "EXIF:GPSLongitude")]
     #[serde(default)]
     pub gps_longitude: Option<String>,
+    /// UTC date of the GPS fix, e.g. `2023:01:04`.
+    #[serde(rename = "EXIF:GPSDateStamp")]
+    #[serde(default)]
+    pub gps_date_stamp: Option<String>,
+    /// UTC time of the GPS fix, e.g. `08:15:00`.
+    #[serde(rename = "EXIF:GPSTimeStamp")]
+    #[serde(default)]
+    pub gps_time_stamp: Option<String>,
+    /// Set when exiftool could not make sense of the file at all.
+    #[serde(rename = "ExifTool:Error")]
+    #[serde(default)]
+    pub exiftool_error: Option<String>,
+    /// Capture date found by the date source chain when it settled on
+    /// something other than the embedded dates, such as the file name. Takes
+    /// precedence over the embedded dates.
+    #[serde(skip)]
+    pub fallback_date: Option<NaiveDateTime>,
+    /// Capture time with its UTC offset, as resolved by
+    /// [`crate::timezone::TimeZones::resolve`].
+    #[serde(skip)]
+    pub capture_time: Option<DateTime<FixedOffset>>,
 }
 
-pub fn get_exif(path: &Path) -> Result<Exif> {
-    let teststr = Command::new("exiftool")
-        .arg("-json")
-        .arg("-G")
-        .arg(
-            path.to_str()
-                .ok_or_else(|| anyhow!("Invalid path provided"))?,
+impl Exif {
+    /// Wall-clock capture time of the photo or video in the display zone, once
+    /// a date source has been settled on and `capture_time` resolved from it.
+    pub fn capture_date(&self) -> Option<NaiveDateTime> {
+        self.capture_time.map(|time| time.naive_local())
+    }
+
+    /// Whether the file's own metadata records when it was captured, as an
+    /// EXIF or a QuickTime date.
+    pub fn has_embedded_date(&self) -> bool {
+        self.date_time_original
+            .or(self.create_date)
+            .or(self.quicktime_create_date)
+            .or(self.quicktime_media_create_date)
+            .is_some()
+    }
+
+    /// The offset recorded in `OffsetTimeOriginal`, if it parses.
+    pub fn offset_time(&self) -> Option<FixedOffset> {
+        parse_offset(self.offset_time_original.as_deref()?).ok()
+    }
+
+    /// The offset implied by the difference between the camera's clock and
+    /// the UTC timestamp of the GPS fix, rounded to the nearest quarter hour.
+    pub fn gps_offset(&self) -> Option<FixedOffset> {
+        let local = self.date_time_original?;
+        let time = self.gps_time_stamp.as_deref()?;
+        let utc = NaiveDateTime::parse_from_str(
+            &format!(
+                "{} {}",
+                self.gps_date_stamp.as_deref()?.trim(),
+                time.split('.').next()?.trim()
+            ),
+            "%Y:%m:%d %H:%M:%S",
         )
-        .output()?
-        .stdout;
-    let stdout = String::from_utf8(teststr)?;
+        .ok()?;
+
+        let quarters = ((local - utc).num_seconds() as f64 / 900.0).round() as i32;
+        if quarters.abs() > 14 * 4 {
+            return None;
+        }
+
+        FixedOffset::east_opt(quarters * 900)
+    }
+
+    pub fn camera_make(&self) -> Option<&String> {
+        self.make.as_ref().or(self.quicktime_make.as_ref())
+    }
 
-    // println!("{}", stdout);
-    let mut g: Vec<Exif> = serde_json::from_str(&stdout)?;
+    pub fn camera_model(&self) -> Option<&String> {
+        self.model.as_ref().or(self.quicktime_model.as_ref())
+    }
 
-    Ok(g.remove(0))
+    /// Identifier that Apple devices share between the image and the video of
+    /// a Live Photo.
+    pub fn content_identifier(&self) -> Option<&String> {
+        self.makernotes_content_identifier
+            .as_ref()
+            .or(self.quicktime_content_identifier.as_ref())
+    }
 }
 
-pub fn write_exif(path: &Path, photo: &Photo) -> std::io::Result<()> {
-    let mut command = &mut Command::new("exiftool");
+/// The file is not in a format the metadata backend can read.
+#[derive(Debug)]
+pub struct UnsupportedFormatError(pub String);
+
+impl std::fmt::Display for UnsupportedFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unsupported file format: {}", self.0)
+    }
+}
 
-    command = command.arg("-overwrite_original");
+impl std::error::Error for UnsupportedFormatError {}
 
-    if let Some(original_filename) = photo.original_filename.as_ref() {
-        command = command.arg(format!("-OriginalFileName={}", original_filename));
+/// The backend cannot record tags inside this file, so they belong in a
+/// sidecar instead.
+#[derive(Debug)]
+pub struct UnwritableError(pub String);
 
-        println!(
-            "\x1b[36mVerbose (write_exif\x1b[35;1m {}\x1b[36m):\x1b[0m Adding tag 'OriginalFileName': \x1b[35;1m{}\x1b[0m",
-            photo.input_path.to_string_lossy(),
-            original_filename
-        );
+impl std::fmt::Display for UnwritableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unable to write tags into {}", self.0)
+    }
+}
+
+impl std::error::Error for UnwritableError {}
+
+/// Tags photobot records on every imported copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    OriginalFileName,
+    Album,
+    /// Only written when a clock correction changed the capture date.
+    DateTimeOriginal,
+    /// Like `DateTimeOriginal`, when the file has a `CreateDate`.
+    CreateDate,
+}
+
+impl Tag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tag::OriginalFileName => "OriginalFileName",
+            Tag::Album => "Album",
+            Tag::DateTimeOriginal => "DateTimeOriginal",
+            Tag::CreateDate => "CreateDate",
+        }
+    }
+}
+
+/// Collects the tags to write on the imported copy of `photo`.
+pub fn photo_tags(photo: &Photo) -> Vec<(Tag, String)> {
+    let mut tags = Vec::new();
+
+    if let Some(original_filename) = photo.original_filename.as_ref() {
+        tags.push((Tag::OriginalFileName, original_filename.clone()));
     }
 
     if let Some(album) = photo.exif.album.as_ref() {
-        command = command.arg(format!("-album={}", album));
-        println!(
-            "\x1b[36mVerbose (write_exif\x1b[35;1m {}\x1b[36m):\x1b[0m Adding tag 'Album': \x1b[35;1m{}\x1b[0m",
-            photo.input_path.to_string_lossy(),
-            album
+        tags.push((Tag::Album, album.clone()));
+    }
+
+    for (tag, date) in &photo.corrected_dates {
+        tags.push((*tag, format_exif_date(*date)));
+    }
+
+    for (tag, value) in &tags {
+        trace!(
+            path = %photo.input_path.display(),
+            tag = tag.name(),
+            %value,
+            "Adding tag"
         );
     }
-    match path.to_str() {
-        Some(s) => command.arg(s).output()?,
-        None => return Err(std::io::Error::from(ErrorKind::InvalidFilename)),
+
+    tags
+}
+
+/// Where the XMP sidecar of the file at `path` lives: `IMG_0001.jpg.xmp`, so
+/// that RAW+JPEG pairs sharing a stem keep separate sidecars.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".xmp");
+    PathBuf::from(name)
+}
+
+/// Records `tags` in an XMP sidecar next to `path`, leaving the file itself
+/// untouched.
+pub fn write_sidecar(path: &Path, tags: &[(Tag, String)]) -> Result<()> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+
+    std::fs::write(sidecar_path(path), xmp::build(tags))?;
+    Ok(())
+}
+
+/// Overlays metadata from an existing XMP sidecar next to `path`, either
+/// `IMG_0001.jpg.xmp` or `IMG_0001.xmp`, so that edits made in Lightroom or
+/// darktable take precedence over what is stored in the file itself.
+pub fn merge_sidecar(path: &Path, exif: &mut Exif) -> Result<()> {
+    let Some(sidecar) = [sidecar_path(path), path.with_extension("xmp")]
+        .into_iter()
+        .find(|p| p.is_file())
+    else {
+        return Ok(());
+    };
+
+    trace!(
+        path = %path.display(),
+        sidecar = %sidecar.display(),
+        "Reading sidecar"
+    );
+
+    let Some(packet) = xmp::find_packet(&std::fs::read(&sidecar)?) else {
+        return Ok(());
     };
 
+    if let Some(date) = packet.get("DateCreated") {
+        exif.xmp_date_created = Some(date);
+    }
+
+    if let Some((date, subsec, offset)) = packet
+        .get("DateTimeOriginal")
+        .and_then(|date| xmp::parse_date(&date))
+    {
+        exif.date_time_original = Some(date);
+        exif.sub_sec_time_original = subsec;
+        exif.offset_time_original = offset;
+    }
+
+    if let Some((date, _, _)) = packet
+        .get("DateTimeDigitized")
+        .and_then(|date| xmp::parse_date(&date))
+    {
+        exif.create_date = Some(date);
+    }
+
+    for (field, name) in [
+        (&mut exif.make, "Make"),
+        (&mut exif.model, "Model"),
+        (&mut exif.album, "Album"),
+        (&mut exif.original_filename, "OriginalFileName"),
+    ] {
+        if let Some(value) = packet.get(name) {
+            *field = Some(value);
+        }
+    }
+
     Ok(())
 }
+
+/// Reads and writes photo metadata.
+pub trait MetadataBackend: Send + Sync {
+    fn read(&self, path: &Path) -> Result<Exif>;
+
+    /// Records `tags` in the file at `path`.
+    fn write(&self, path: &Path, tags: &[(Tag, String)]) -> Result<()>;
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataBackendKind {
+    /// Built-in reader, falling back to exiftool for formats it cannot handle
+    Auto,
+    /// Built-in reader only; exiftool is never run
+    Native,
+    /// exiftool for every file
+    Exiftool,
+}
+
+pub fn new_backend(kind: MetadataBackendKind) -> Box<dyn MetadataBackend> {
+    match kind {
+        MetadataBackendKind::Auto => Box::new(AutoBackend {
+            native: NativeBackend,
+            exiftool: ExiftoolBackend::default(),
+        }),
+        MetadataBackendKind::Native => Box::new(NativeBackend),
+        MetadataBackendKind::Exiftool => Box::new(ExiftoolBackend::default()),
+    }
+}
+
+/// Uses the native backend where it can and exiftool for everything else:
+/// files the native reader does not understand, files where it finds no
+/// capture date, and writes it cannot perform. Without exiftool installed,
+/// those writes fail as the native backend's did.
+struct AutoBackend {
+    native: NativeBackend,
+    exiftool: ExiftoolBackend,
+}
+
+impl MetadataBackend for AutoBackend {
+    fn read(&self, path: &Path) -> Result<Exif> {
+        match self.native.read(path) {
+            Ok(exif) if exif.has_embedded_date() => Ok(exif),
+            Ok(exif) => Ok(self.exiftool.read(path).unwrap_or(exif)),
+            Err(_) => self.exiftool.read(path),
+        }
+    }
+
+    fn write(&self, path: &Path, tags: &[(Tag, String)]) -> Result<()> {
+        self.native.write(path, tags).or_else(|native| {
+            self.exiftool.write(path, tags).map_err(|e| {
+                if e.is::<ExiftoolUnavailableError>() {
+                    native
+                } else {
+                    e
+                }
+            })
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use super::{Exif, MetadataBackend, Tag, UnsupportedFormatError};
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// exiftool could not be run, most likely because it is not installed.
+#[derive(Debug)]
+pub struct ExiftoolUnavailableError(String);
+
+impl std::fmt::Display for ExiftoolUnavailableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unable to start exiftool: {}", self.0)
+    }
+}
+
+impl std::error::Error for ExiftoolUnavailableError {}
+
+/// A long-lived `exiftool -stay_open True -@ -` process. Arguments are fed one
+/// per line on stdin, and each `-execute` is answered on stdout with the
+/// command's output followed by a `{ready}` line.
+struct ExiftoolProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExiftoolProcess {
+    fn spawn() -> Result<ExiftoolProcess> {
+        let mut child = Command::new("exiftool")
+            .args(["-stay_open", "True", "-@", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| ExiftoolUnavailableError(e.to_string()))?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("exiftool stdin unavailable"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("exiftool stdout unavailable"))?;
+
+        Ok(ExiftoolProcess {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    fn execute(&mut self, args: &[String]) -> Result<String> {
+        for arg in args {
+            if arg.contains('\n') {
+                return Err(anyhow!("exiftool arguments cannot contain newlines"));
+            }
+            writeln!(self.stdin, "{}", arg)?;
+        }
+        writeln!(self.stdin, "-execute")?;
+        self.stdin.flush()?;
+
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(anyhow!("exiftool exited unexpectedly"));
+            }
+            if line.trim_end() == "{ready}" {
+                return Ok(response);
+            }
+            response.push_str(&line);
+        }
+    }
+}
+
+impl Drop for ExiftoolProcess {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "-stay_open\nFalse");
+        let _ = self.stdin.flush();
+        if self.child.try_wait().ok().flatten().is_none() {
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Runs every read and write through one shared exiftool process, started on
+/// first use and restarted if it dies.
+#[derive(Default)]
+pub struct ExiftoolBackend {
+    process: Mutex<Option<ExiftoolProcess>>,
+}
+
+impl ExiftoolBackend {
+    fn execute(&self, args: &[String]) -> Result<String> {
+        let mut process = self.process.lock().map_err(|e| anyhow!(e.to_string()))?;
+
+        // A failed command most likely means exiftool crashed; start a fresh
+        // process and try once more.
+        execute_in(&mut process, args).or_else(|e| {
+            warn!("Restarting exiftool after error: {:#}", e);
+            execute_in(&mut process, args)
+        })
+    }
+}
+
+fn execute_in(process: &mut Option<ExiftoolProcess>, args: &[String]) -> Result<String> {
+    let mut running = match process.take() {
+        Some(running) => running,
+        None => ExiftoolProcess::spawn()?,
+    };
+
+    let result = running.execute(args);
+
+    if result.is_ok() {
+        *process = Some(running);
+    } else {
+        let _ = running.child.kill();
+    }
+
+    result
+}
+
+fn path_arg(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("Invalid path provided"))
+}
+
+impl MetadataBackend for ExiftoolBackend {
+    fn read(&self, path: &Path) -> Result<Exif> {
+        let stdout = self.execute(&["-json".to_string(), "-G".to_string(), path_arg(path)?])?;
+
+        if stdout.trim().is_empty() {
+            return Err(anyhow!(
+                "exiftool returned no metadata for {}",
+                path.display()
+            ));
+        }
+
+        let g: Vec<Exif> = serde_json::from_str(&stdout)?;
+
+        let exif = g
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("exiftool returned no metadata for {}", path.display()))?;
+
+        match &exif.exiftool_error {
+            Some(error) => {
+                Err(UnsupportedFormatError(format!("{}: {}", path.display(), error)).into())
+            }
+            None => Ok(exif),
+        }
+    }
+
+    fn write(&self, path: &Path, tags: &[(Tag, String)]) -> Result<()> {
+        let mut args = vec!["-overwrite_original".to_string()];
+
+        for (tag, value) in tags {
+            args.push(match tag {
+                Tag::OriginalFileName => format!("-OriginalFileName={}", value),
+                Tag::Album => format!("-album={}", value),
+                Tag::DateTimeOriginal => format!("-DateTimeOriginal={}", value),
+                Tag::CreateDate => format!("-CreateDate={}", value),
+            });
+        }
+
+        args.push(path_arg(path)?);
+
+        let stdout = self.execute(&args)?;
+
+        if !stdout.contains("1 image files updated") {
+            return Err(anyhow!(
+                "exiftool failed to update {}: {}",
+                path.display(),
+                stdout.trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
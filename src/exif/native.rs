@@ -0,0 +1,226 @@
+use super::{xmp, Exif, MetadataBackend, UnsupportedFormatError, UnwritableError};
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use kamadak_exif::{In, Reader, Tag, Value};
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Reads EXIF from JPEG, TIFF-based RAW, HEIF and PNG files and XMP packets
+/// embedded in any file, without spawning external tools. Writing is limited
+/// to JPEG files that carry no XMP packet yet; other files fail with
+/// [`UnwritableError`].
+pub struct NativeBackend;
+
+fn text(exif: &kamadak_exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+fn date(exif: &kamadak_exif::Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let value = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first()?.clone(),
+        _ => return None,
+    };
+    let dt = kamadak_exif::DateTime::from_ascii(&value).ok()?;
+
+    NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)?.and_hms_opt(
+        dt.hour as u32,
+        dt.minute as u32,
+        dt.second as u32,
+    )
+}
+
+/// Formats GPS coordinates the way exiftool prints them, e.g.
+/// `37 deg 46' 29.64"`.
+fn gps(exif: &kamadak_exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(dms) if dms.len() == 3 => Some(format!(
+            "{} deg {}' {:.2}\"",
+            dms[0].to_f64(),
+            dms[1].to_f64(),
+            dms[2].to_f64()
+        )),
+        _ => None,
+    }
+}
+
+/// Formats the GPS time of day the way exiftool prints it, e.g. `08:15:00`.
+fn gps_time(exif: &kamadak_exif::Exif) -> Option<String> {
+    match &exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value {
+        Value::Rational(hms) if hms.len() == 3 => Some(format!(
+            "{:02}:{:02}:{:02}",
+            hms[0].to_f64() as u32,
+            hms[1].to_f64() as u32,
+            hms[2].to_f64() as u32
+        )),
+        _ => None,
+    }
+}
+
+impl MetadataBackend for NativeBackend {
+    fn read(&self, path: &Path) -> Result<Exif> {
+        let mut reader = BufReader::new(File::open(path)?);
+        // Scans and screenshots often carry no EXIF at all; their dates can
+        // still come from XMP or the date source chain.
+        let mut exif = match Reader::new().read_from_container(&mut reader) {
+            Ok(raw) => Exif {
+                date_time_original: date(&raw, Tag::DateTimeOriginal),
+                create_date: date(&raw, Tag::DateTimeDigitized),
+                sub_sec_time_original: text(&raw, Tag::SubSecTimeOriginal),
+                offset_time_original: text(&raw, Tag::OffsetTimeOriginal),
+                make: text(&raw, Tag::Make),
+                model: text(&raw, Tag::Model),
+                serial_number: text(&raw, Tag::BodySerialNumber),
+                gps_latitude: gps(&raw, Tag::GPSLatitude),
+                gps_longitude: gps(&raw, Tag::GPSLongitude),
+                gps_date_stamp: text(&raw, Tag::GPSDateStamp),
+                gps_time_stamp: gps_time(&raw),
+                ..Default::default()
+            },
+            Err(kamadak_exif::Error::NotFound(_)) => Exif::default(),
+            Err(kamadak_exif::Error::InvalidFormat("Unknown image format")) => {
+                return Err(UnsupportedFormatError(path.display().to_string()).into())
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Unable to read EXIF from {}: {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+
+        if let Some(packet) = xmp::read_embedded(path)? {
+            exif.album = packet.get("Album");
+            exif.original_filename = packet.get("OriginalFileName");
+            exif.xmp_date_created = packet.get("DateCreated");
+            exif.xmp_create_date = packet.get("CreateDate");
+        }
+
+        Ok(exif)
+    }
+
+    fn write(&self, path: &Path, tags: &[(super::Tag, String)]) -> Result<()> {
+        if tags
+            .iter()
+            .any(|(tag, _)| matches!(tag, super::Tag::DateTimeOriginal | super::Tag::CreateDate))
+        {
+            return Err(UnwritableError(format!(
+                "{}: the native backend cannot rewrite EXIF dates",
+                path.display()
+            ))
+            .into());
+        }
+
+        xmp::embed_in_jpeg(path, &xmp::build(tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exif::Tag as PhotoTag;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn fixture_date() -> Option<NaiveDateTime> {
+        NaiveDate::from_ymd_opt(2023, 1, 4).and_then(|d| d.and_hms_opt(10, 15, 0))
+    }
+
+    #[test]
+    fn reads_exif_from_jpeg_tiff_and_heic() {
+        for name in ["photo.jpg", "photo.tif", "photo.heic"] {
+            let exif = NativeBackend.read(&fixture(name)).unwrap();
+
+            assert_eq!(exif.date_time_original, fixture_date(), "{}", name);
+            assert_eq!(
+                exif.sub_sec_time_original.as_deref(),
+                Some("25"),
+                "{}",
+                name
+            );
+            assert_eq!(exif.make.as_deref(), Some("Fixture"), "{}", name);
+            assert_eq!(exif.model.as_deref(), Some("Camera One"), "{}", name);
+        }
+
+        let exif = NativeBackend.read(&fixture("createdate.jpg")).unwrap();
+        assert_eq!(exif.date_time_original, None);
+        assert_eq!(exif.create_date, fixture_date());
+    }
+
+    #[test]
+    fn embedded_xmp_reads_back_beside_the_exif() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        std::fs::copy(fixture("photo.jpg"), &path).unwrap();
+
+        let tags = [
+            (PhotoTag::OriginalFileName, "IMG_0001.JPG".to_string()),
+            (PhotoTag::Album, "Trips & Tours".to_string()),
+        ];
+        NativeBackend.write(&path, &tags).unwrap();
+
+        let exif = NativeBackend.read(&path).unwrap();
+        assert_eq!(exif.original_filename.as_deref(), Some("IMG_0001.JPG"));
+        assert_eq!(exif.album.as_deref(), Some("Trips & Tours"));
+        assert_eq!(exif.date_time_original, fixture_date());
+        assert_eq!(exif.model.as_deref(), Some("Camera One"));
+
+        // The image data follows unchanged.
+        let original = std::fs::read(fixture("photo.jpg")).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(written.ends_with(&original[original.len() - 64..]));
+        assert!(written.len() > original.len());
+
+        // A packet is never merged into an existing one.
+        assert!(NativeBackend
+            .write(&path, &tags)
+            .unwrap_err()
+            .is::<UnwritableError>());
+        assert_eq!(std::fs::read(&path).unwrap(), written);
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = dir.path().join("photo.jpg");
+        let tiff = dir.path().join("photo.tif");
+        std::fs::copy(fixture("photo.jpg"), &jpeg).unwrap();
+        std::fs::copy(fixture("photo.tif"), &tiff).unwrap();
+
+        let date = [(
+            PhotoTag::DateTimeOriginal,
+            "2023:01:04 11:15:00".to_string(),
+        )];
+        assert!(NativeBackend
+            .write(&jpeg, &date)
+            .unwrap_err()
+            .is::<UnwritableError>());
+        assert_eq!(
+            std::fs::read(&jpeg).unwrap(),
+            std::fs::read(fixture("photo.jpg")).unwrap()
+        );
+
+        let album = [(PhotoTag::Album, "Trips".to_string())];
+        assert!(NativeBackend
+            .write(&tiff, &album)
+            .unwrap_err()
+            .is::<UnwritableError>());
+        assert_eq!(
+            std::fs::read(&tiff).unwrap(),
+            std::fs::read(fixture("photo.tif")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use super::{Tag, UnwritableError};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+
+use crate::clock::parse_date as parse_exif_date;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Embedded XMP packets sit near the start of the files photobot handles, so
+/// only this many bytes are searched for one.
+const XMP_SCAN_LIMIT: u64 = 1024 * 1024;
+
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const PHOTOBOT_NS: &str = "https://github.com/aaginskiy/photobot/ns/1.0/";
+
+/// The raw text of an `<x:xmpmeta>` packet.
+pub struct XmpPacket(String);
+
+impl XmpPacket {
+    /// Returns the value of the first property called `name`, in any
+    /// namespace, written either as an attribute or as a simple element.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let haystack = self.0.to_ascii_lowercase();
+        let name = name.to_ascii_lowercase();
+
+        let attribute = format!(":{}=\"", name);
+        if let Some(start) = haystack.find(&attribute).map(|i| i + attribute.len()) {
+            let end = start + haystack[start..].find('"')?;
+            return Some(unescape(&self.0[start..end]));
+        }
+
+        let element = format!(":{}>", name);
+        let start = haystack.find(&element)? + element.len();
+        let end = start + haystack[start..].find('<')?;
+        let value = unescape(self.0[start..end].trim());
+
+        // Structured values such as rdf:Alt wrap the text in rdf:li elements.
+        if value.is_empty() {
+            let li = start + haystack[start..].find("<rdf:li")?;
+            let start = li + haystack[li..].find('>')? + 1;
+            let end = start + haystack[start..].find('<')?;
+            return Some(unescape(self.0[start..end].trim())).filter(|v| !v.is_empty());
+        }
+
+        Some(value)
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+pub fn find_packet(data: &[u8]) -> Option<XmpPacket> {
+    let start = find_subslice(data, b"<x:xmpmeta")?;
+    let end = start + find_subslice(&data[start..], b"</x:xmpmeta>")? + b"</x:xmpmeta>".len();
+
+    Some(XmpPacket(
+        String::from_utf8_lossy(&data[start..end]).into_owned(),
+    ))
+}
+
+pub fn read_embedded(path: &Path) -> Result<Option<XmpPacket>> {
+    let mut data = Vec::new();
+    File::open(path)?
+        .take(XMP_SCAN_LIMIT)
+        .read_to_end(&mut data)?;
+
+    Ok(find_packet(&data))
+}
+
+/// Parses an XMP date such as `2023-01-04T10:15:00.25+01:00` into the local
+/// time as written, its fractional seconds and its UTC offset.
+pub fn parse_date(value: &str) -> Option<(NaiveDateTime, Option<String>, Option<String>)> {
+    let value = value.trim();
+    let (local, offset) = match value.get(10..)?.find(['Z', '+', '-']) {
+        Some(i) => (&value[..10 + i], Some(value[10 + i..].to_string())),
+        None => (value, None),
+    };
+    let (local, subsec) = match local.split_once('.') {
+        Some((local, subsec)) => (local, Some(subsec.to_string())),
+        None => (local, None),
+    };
+
+    NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .map(|date| (date, subsec, offset))
+}
+
+fn property(tag: Tag) -> &'static str {
+    match tag {
+        Tag::OriginalFileName => "photobot:OriginalFileName",
+        Tag::Album => "xmpDM:album",
+        Tag::DateTimeOriginal => "exif:DateTimeOriginal",
+        // EXIF's CreateDate is DateTimeDigitized in the XMP exif namespace.
+        Tag::CreateDate => "exif:DateTimeDigitized",
+    }
+}
+
+/// Converts a tag value to its XMP form; dates use ISO 8601 rather than the
+/// EXIF `YYYY:MM:DD HH:MM:SS` layout.
+fn xmp_value(tag: Tag, value: &str) -> String {
+    match tag {
+        Tag::DateTimeOriginal | Tag::CreateDate => parse_exif_date(value)
+            .map(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string())
+            .unwrap_or_else(|_| value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+/// Builds a complete XMP packet holding `tags`.
+pub fn build(tags: &[(Tag, String)]) -> String {
+    let properties = tags
+        .iter()
+        .map(|(tag, value)| {
+            format!(
+                "   <{p}>{}</{p}>\n",
+                escape(&xmp_value(*tag, value)),
+                p = property(*tag)
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"\n",
+            "    xmlns:xmpDM=\"http://ns.adobe.com/xmp/1.0/DynamicMedia/\"\n",
+            "    xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"\n",
+            "    xmlns:photobot=\"{}\">\n",
+            "{}",
+            "  </rdf:Description>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        PHOTOBOT_NS, properties
+    )
+}
+
+/// Inserts `packet` into a JPEG file as an APP1 segment. Files that are not
+/// JPEGs or already carry XMP are rejected rather than merged.
+pub fn embed_in_jpeg(path: &Path, packet: &str) -> Result<()> {
+    let data = std::fs::read(path)?;
+
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err(UnwritableError(format!("{}: not a JPEG file", path.display())).into());
+    }
+
+    // Keep JFIF and EXIF segments first, as readers expect them there.
+    let mut insert_at = 2;
+    while insert_at + 4 <= data.len() && data[insert_at] == 0xff {
+        let marker = data[insert_at + 1];
+        let length = u16::from_be_bytes([data[insert_at + 2], data[insert_at + 3]]) as usize;
+        let segment = insert_at + 4..insert_at + 2 + length;
+
+        if marker == 0xe1
+            && data
+                .get(segment.clone())
+                .is_some_and(|s| s.starts_with(JPEG_XMP_HEADER))
+        {
+            return Err(
+                UnwritableError(format!("{}: already contains XMP", path.display())).into(),
+            );
+        }
+        if marker != 0xe0 && marker != 0xe1 {
+            break;
+        }
+        insert_at = segment.end;
+    }
+
+    let length = 2 + JPEG_XMP_HEADER.len() + packet.len();
+    if length > u16::MAX as usize {
+        return Err(UnwritableError(format!(
+            "{}: XMP packet too large for a JPEG segment",
+            path.display()
+        ))
+        .into());
+    }
+
+    let tmp_path = path.with_extension("photobot-tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(&data[..insert_at])?;
+    tmp.write_all(&[0xff, 0xe1])?;
+    tmp.write_all(&(length as u16).to_be_bytes())?;
+    tmp.write_all(JPEG_XMP_HEADER)?;
+    tmp.write_all(packet.as_bytes())?;
+    tmp.write_all(&data[insert_at..])?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::progress::{suspend, ProgressOutput};
+use anyhow::{anyhow, Result};
+use std::io::{self, IsTerminal, Write};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines, colored when stderr is a terminal
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct LogArgs {
+    /// Log more: -v for each file's progress, -vv for everything
+    #[arg(long, short, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Log less: -q for warnings and errors only, -qq for errors only
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    quiet: u8,
+    /// How log lines on stderr are formatted
+    #[arg(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// How progress is shown: not at all with `-q`, and as log events when
+    /// logging JSON.
+    pub fn progress(&self) -> ProgressOutput {
+        match (self.quiet, self.log_format) {
+            (0, LogFormat::Text) => ProgressOutput::Text,
+            (0, LogFormat::Json) => ProgressOutput::Log,
+            _ => ProgressOutput::Hidden,
+        }
+    }
+
+    fn level(&self) -> Level {
+        match (self.verbose, self.quiet) {
+            (0, 0) => Level::INFO,
+            (1, _) => Level::DEBUG,
+            (_, 0) => Level::TRACE,
+            (_, 1) => Level::WARN,
+            _ => Level::ERROR,
+        }
+    }
+}
+
+/// Sends log events to stderr, leaving stdout to plans and summaries.
+/// `RUST_LOG`, when set, takes precedence over `-v` and `-q`.
+pub fn init_logging(args: &LogArgs) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!(
+            "warn,photobot={}",
+            args.level().as_str().to_lowercase()
+        ))
+    });
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| LogWriter);
+
+    match args.log_format {
+        LogFormat::Text => builder
+            .with_ansi(use_color(&std::io::stderr()))
+            .without_time()
+            .with_target(false)
+            .try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow!("Unable to set up logging: {}", e))
+}
+
+/// Writes log lines to stderr, moving any progress bar out of their way.
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Whether to color output written to `stream`: only for terminals, and never
+/// when `NO_COLOR` is set.
+pub fn use_color<S: IsTerminal>(stream: &S) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
@@ -1,28 +1,67 @@
 #![feature(path_file_prefix)]
 #![feature(fs_try_exists)]
-#![feature(io_error_more)]
 #![feature(result_option_inspect)]
+mod catalog;
+mod checksum;
+mod clock;
+mod config;
+mod datesource;
 mod exif;
-mod photohashdb;
+mod logging;
+mod pairs;
+mod plan;
+mod progress;
+mod quarantine;
+mod rebuild;
+mod template;
+mod timezone;
+mod transfer;
+mod verify;
 
-use adler32::adler32;
 use anyhow::{anyhow, Result};
+use catalog::{Catalog, CatalogEntry};
+use checksum::{checksum_file, checksum_file_with_legacy_key, Checksum};
+use chrono::NaiveDateTime;
 use clap::Parser;
-use exif::{get_exif, write_exif, Exif};
-use globset::{Glob, GlobMatcher};
-use once_cell::sync::{Lazy, OnceCell};
-use photohashdb::load_db;
-use pickledb::PickleDb;
-use std::fs::{copy, File};
-use std::io::BufReader;
+use clock::ClockCorrections;
+use config::load_config;
+use datesource::{choose_date_source, DateSource, DEFAULT_DATE_SOURCES};
+use exif::{
+    merge_sidecar, new_backend, photo_tags, sidecar_path, write_sidecar, Exif, MetadataBackend,
+    MetadataBackendKind, Tag, UnwritableError,
+};
+use globset::{GlobBuilder, GlobMatcher};
+use logging::{init_logging, LogArgs};
+use pairs::group_pairs;
+use plan::{group_outcomes, plan_photos, print_plan, print_summary, Outcome, PlanEntry};
+use progress::{Progress, ProgressOutput};
+use quarantine::quarantine;
+use rayon::prelude::*;
+use rebuild::{print_rebuild, rebuild_index};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::{self};
+use template::{Template, TemplateContext};
+use timezone::{parse_display_zone, parse_offset, TimeZones};
+use tracing::{debug, error, trace, warn};
+use transfer::{transfer, LinkMode, TransferMode};
+use verify::{print_verification, verify_library, Selection};
 use walkdir::WalkDir;
 
-static GLOB_MATCHER: Lazy<GlobMatcher> =
-    Lazy::new(|| Glob::new("**/*.{jpg,jpeg}").unwrap().compile_matcher());
+/// File extensions imported when neither `--extensions` nor the config file
+/// overrides them. Matching is case-insensitive.
+const DEFAULT_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "heic", "heif", "png", "tif", "tiff", "dng", "cr2", "cr3", "nef", "arw", "raf",
+    "orf",
+];
 
-static PHOTOHASH_DB: OnceCell<std::sync::Mutex<PickleDb>> = OnceCell::new();
+const DEFAULT_VIDEO_EXTENSIONS: &[&str] = &["mov", "mp4", "m4v", "3gp"];
+
+/// The catalog of the library being imported into, open for the duration of
+/// an `import` or `test` run.
+static CATALOG: std::sync::Mutex<Option<Catalog>> = std::sync::Mutex::new(None);
 
 #[derive(Parser)] // requires `derive` feature
 #[command(name = "photobot")]
@@ -30,6 +69,8 @@ static PHOTOHASH_DB: OnceCell<std::sync::Mutex<PickleDb>> = OnceCell::new();
 enum Cargo {
     Import(Import),
     Test(Test),
+    RebuildIndex(RebuildIndex),
+    Verify(Verify),
 }
 
 #[derive(clap::Args)]
@@ -40,20 +81,136 @@ struct Import {
     output: PathBuf,
     #[arg(long, short)]
     album_from_filename: bool,
+    #[command(flatten)]
+    naming: NamingArgs,
+    /// Write tags to an XMP sidecar instead of modifying the imported file.
+    /// Files the metadata backend cannot write into always get one
+    #[arg(long)]
+    xmp_sidecar: bool,
+    /// Write dates changed by a clock correction back into the imported copy
+    #[arg(long)]
+    write_corrected_dates: bool,
+    /// Number of files to process in parallel [default: number of CPUs]
+    #[arg(long, short, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+    /// Remove each source file once its library copy has been verified
+    #[arg(long = "move", conflicts_with = "link")]
+    move_files: bool,
+    /// Link library files to their sources instead of copying, falling back to
+    /// a copy where the filesystem can't. Metadata goes to XMP sidecars.
+    #[arg(long, value_enum)]
+    link: Option<LinkMode>,
+    /// Write what happened to each file as JSON to this file
+    #[arg(long)]
+    report: Option<PathBuf>,
+    /// Set aside an unreadable catalog and start an empty one instead of
+    /// stopping
+    #[arg(long)]
+    rebuild_db: bool,
+    #[command(flatten)]
+    logging: LogArgs,
     /// Files or directories to organize
     paths: Vec<PathBuf>,
 }
 
+// Options deciding which files belong in a library and where they go. A plain
+// comment, as clap would otherwise take it as the about text of every command
+// flattening it in.
+#[derive(clap::Args)]
+struct NamingArgs {
+    /// Output path template, e.g. "{year}/{month:02}/{camera}/{date}_{time}"
+    #[arg(long, short)]
+    template: Option<String>,
+    /// JSON configuration file
+    #[arg(long, short)]
+    config: Option<PathBuf>,
+    /// Comma-separated list of file extensions to import
+    #[arg(long, short, value_delimiter = ',')]
+    extensions: Option<Vec<String>>,
+    /// Comma-separated list of video file extensions to import
+    #[arg(long, value_delimiter = ',')]
+    video_extensions: Option<Vec<String>>,
+    /// Where to file videos
+    #[arg(long, value_enum, default_value_t = VideoLayout::Inline)]
+    videos: VideoLayout,
+    /// How photo metadata is read and written
+    #[arg(long, value_enum, default_value_t = MetadataBackendKind::Auto)]
+    metadata_backend: MetadataBackendKind,
+    /// Where files that cannot be imported are copied to [default:
+    /// <OUTPUT>/unsorted]
+    #[arg(long)]
+    unsorted_dir: Option<PathBuf>,
+    /// UTC offset all camera clocks were set to, e.g. +02:00, overriding any
+    /// offset recorded in the files
+    #[arg(long, value_parser = parse_offset)]
+    camera_tz: Option<chrono::FixedOffset>,
+    /// Zone to name files in: "local" or an offset such as +02:00 [default:
+    /// each capture's own zone]
+    #[arg(long)]
+    display_tz: Option<String>,
+    /// Comma-separated sources to take capture dates from, in order of
+    /// preference [default: exif,xmp,iptc,filename,folder,mtime]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    date_sources: Option<Vec<DateSource>>,
+}
+
 #[derive(clap::Args)]
 #[command(author, version, about, long_about = None)]
 struct Test {
-    /// Output directory for photos
-    #[arg(long, short)]
+    #[command(flatten)]
+    import: Import,
+    /// Write the plan as JSON to this file
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+/// Rebuild the catalog from the files already in a library
+#[derive(clap::Args)]
+struct RebuildIndex {
+    /// Library to index, as given to `import --output`
     output: PathBuf,
-    #[arg(long, short)]
-    album_from_filename: bool,
-    /// Files or directories to organize
-    paths: Vec<PathBuf>,
+    #[command(flatten)]
+    naming: NamingArgs,
+    /// Number of files to process in parallel [default: number of CPUs]
+    #[arg(long, short, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+    #[command(flatten)]
+    logging: LogArgs,
+}
+
+/// Re-hash library files to find corruption, missing files and files missing from the catalog
+#[derive(clap::Args)]
+struct Verify {
+    /// Library to verify, as given to `import --output`
+    output: PathBuf,
+    /// Only verify this many files, picked at random
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u64).range(1..))]
+    sample: Option<u64>,
+    /// Only verify files that haven't been verified in this many days
+    #[arg(long, value_name = "DAYS")]
+    not_verified_for: Option<u32>,
+    #[command(flatten)]
+    naming: NamingArgs,
+    /// Number of files to process in parallel [default: number of CPUs]
+    #[arg(long, short, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+    #[command(flatten)]
+    logging: LogArgs,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum VideoLayout {
+    /// Alongside the photos, in the same timeline and album folders
+    Inline,
+    /// In a parallel tree under videos/
+    Separate,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Photo,
+    Video,
 }
 
 #[derive(Debug, Clone)]
@@ -62,34 +219,262 @@ pub struct Photo {
     original_filename: Option<String>,
     output_filename: String,
     exif: Exif,
-    _checksum: u32,
+    checksum: Checksum,
+    kind: MediaKind,
+    seq: usize,
+    /// Capture dates fixed by a clock correction, to be written back to the
+    /// imported copy.
+    corrected_dates: Vec<(Tag, NaiveDateTime)>,
+    /// Where the capture date came from.
+    date_source: Option<DateSource>,
+    /// The adler32 checksum, when the catalog has entries migrated from
+    /// `photohash.db` that can only be found by it.
+    legacy_key: Option<u32>,
 }
 
 struct PhotoPath {
     input_path: PathBuf,
     input_dir: PathBuf,
+    /// 1-based position in discovery order, used by the `{seq}` placeholder.
+    seq: usize,
 }
 
 // #[derive(Clone)]
 struct State {
     output_dir: PathBuf,
+    unsorted_dir: PathBuf,
     album_from_filename: bool,
+    /// Destinations assigned during this run, with the checksum of the photo
+    /// that claimed them.
+    claimed: std::sync::Mutex<HashMap<String, Checksum>>,
+    template: Option<Template>,
+    /// Matches every importable file, photos and videos alike.
+    photo_matcher: GlobMatcher,
+    video_matcher: GlobMatcher,
+    video_layout: VideoLayout,
+    metadata: Box<dyn MetadataBackend>,
+    time_zones: TimeZones,
+    clock_corrections: ClockCorrections,
+    write_corrected_dates: bool,
+    date_sources: Vec<DateSource>,
+    /// Whether tags go to XMP sidecars rather than into the imported files.
+    xmp_sidecar: bool,
+    transfer_mode: TransferMode,
+    /// How to show progress once discovery is done.
+    progress: ProgressOutput,
 }
 
-fn main() -> Result<()> {
-    if let Cargo::Import(args) = Cargo::parse() {
-        PHOTOHASH_DB
-            .set(std::sync::Mutex::new(load_db(&args.output)))
-            .map_err(|_e| anyhow!("PhotoHashDB already initialized."))?;
+impl State {
+    /// Settings shared by every command that works out where files belong in
+    /// the library at `output`.
+    fn new(output: &Path, args: &NamingArgs) -> Result<State> {
+        let config = load_config(args.config.as_deref())?;
 
-        let state = State {
-            output_dir: args.output,
-            album_from_filename: args.album_from_filename,
+        let template = args
+            .template
+            .as_ref()
+            .or(config.template.as_ref())
+            .map(|t| Template::parse(t))
+            .transpose()?;
+
+        let extensions = args
+            .extensions
+            .clone()
+            .or(config.extensions)
+            .unwrap_or_else(|| DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect());
+
+        let video_extensions = args
+            .video_extensions
+            .clone()
+            .or(config.video_extensions)
+            .unwrap_or_else(|| {
+                DEFAULT_VIDEO_EXTENSIONS
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect()
+            });
+
+        let time_zones = TimeZones {
+            camera_tz: args.camera_tz,
+            camera_offsets: config
+                .camera_offsets
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(camera, offset)| Ok((camera, parse_offset(&offset)?)))
+                .collect::<Result<_>>()?,
+            display: args
+                .display_tz
+                .as_ref()
+                .or(config.display_tz.as_ref())
+                .map(|zone| parse_display_zone(zone))
+                .transpose()?,
+        };
+
+        let clock_corrections =
+            ClockCorrections::parse(&config.clock_corrections.unwrap_or_default())?;
+
+        Ok(State {
+            output_dir: output.to_path_buf(),
+            unsorted_dir: args
+                .unsorted_dir
+                .clone()
+                .unwrap_or_else(|| output.join("unsorted")),
+            album_from_filename: false,
+            claimed: Default::default(),
+            template,
+            photo_matcher: build_matcher(
+                &[extensions.as_slice(), video_extensions.as_slice()].concat(),
+            )?,
+            video_matcher: build_matcher(&video_extensions)?,
+            video_layout: args.videos,
+            metadata: new_backend(args.metadata_backend),
+            time_zones,
+            clock_corrections,
+            write_corrected_dates: false,
+            date_sources: args
+                .date_sources
+                .clone()
+                .or(config.date_sources)
+                .unwrap_or_else(|| DEFAULT_DATE_SOURCES.to_vec()),
+            xmp_sidecar: false,
+            transfer_mode: TransferMode::Copy,
+            progress: ProgressOutput::Text,
+        })
+    }
+
+    fn for_import(args: &Import) -> Result<State> {
+        let transfer_mode = match (args.move_files, args.link) {
+            (_, Some(link)) => TransferMode::Link(link),
+            (true, None) => TransferMode::Move,
+            (false, None) => TransferMode::Copy,
         };
 
-        if let Ok(_file) = File::open(state.output_dir.join("/photohash.db")) {}
+        Ok(State {
+            album_from_filename: args.album_from_filename,
+            write_corrected_dates: args.write_corrected_dates,
+            xmp_sidecar: args.xmp_sidecar || transfer_mode.is_linked(),
+            transfer_mode,
+            progress: args.logging.progress(),
+            ..State::new(&args.output, &args.naming)?
+        })
+    }
+}
+
+fn build_matcher(extensions: &[String]) -> Result<GlobMatcher> {
+    let extensions = extensions
+        .iter()
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .collect::<Vec<_>>();
+
+    if let Some(invalid) = extensions
+        .iter()
+        .find(|e| e.is_empty() || e.contains(|c: char| "*?[]{},/\\".contains(c)))
+    {
+        return Err(anyhow!("Invalid file extension '{}'", invalid));
+    }
+
+    if extensions.is_empty() {
+        return Err(anyhow!("At least one file extension is required"));
+    }
+
+    Ok(
+        GlobBuilder::new(&format!("**/*.{{{}}}", extensions.join(",")))
+            .case_insensitive(true)
+            .build()?
+            .compile_matcher(),
+    )
+}
+
+fn init_thread_pool(jobs: Option<u16>) -> Result<()> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+
+    if let Some(jobs) = jobs {
+        builder = builder.num_threads(jobs as usize);
+    }
+
+    Ok(builder.build_global()?)
+}
+
+fn main() -> Result<()> {
+    match Cargo::parse() {
+        Cargo::Import(args) => {
+            init_logging(&args.logging)?;
+            init_thread_pool(args.jobs)?;
+            let state = State::for_import(&args)?;
+
+            let mut catalog = Catalog::open_and_back_up(&args.output, args.rebuild_db)?;
+            catalog.begin_batch(&args.paths)?;
+
+            set_catalog(catalog)?;
+
+            let entries = import_photos(&args.paths, &state);
+            close_catalog()?;
+            print_summary(&entries);
 
-        import_photos(&args.paths, &state);
+            if let Some(report) = args.report {
+                std::fs::write(report, serde_json::to_string_pretty(&entries)?)?;
+            }
+        }
+        Cargo::Test(args) => {
+            init_logging(&args.import.logging)?;
+            init_thread_pool(args.import.jobs)?;
+            let state = State::for_import(&args.import)?;
+
+            set_catalog(Catalog::open_read_only(
+                &args.import.output,
+                args.import.rebuild_db,
+            )?)?;
+
+            let plan = plan_photos(&args.import.paths, &state);
+            close_catalog()?;
+            print_plan(&plan);
+
+            if let Some(json) = args.json.or(args.import.report) {
+                std::fs::write(json, serde_json::to_string_pretty(&plan)?)?;
+            }
+        }
+        Cargo::RebuildIndex(args) => {
+            init_logging(&args.logging)?;
+            init_thread_pool(args.jobs)?;
+            let state = State {
+                progress: args.logging.progress(),
+                ..State::new(&args.output, &args.naming)?
+            };
+
+            // Rebuilding replaces whatever the catalog holds, so an
+            // unreadable one is set aside rather than an error.
+            let mut catalog = Catalog::open(&args.output, true)?;
+            let report = rebuild_index(&state, &mut catalog)?;
+            catalog.close()?;
+            print_rebuild(&report);
+        }
+        Cargo::Verify(args) => {
+            init_logging(&args.logging)?;
+            init_thread_pool(args.jobs)?;
+            let state = State {
+                progress: args.logging.progress(),
+                ..State::new(&args.output, &args.naming)?
+            };
+
+            if !Catalog::exists(&args.output) {
+                return Err(anyhow!(
+                    "No catalog in {}. Run `photobot rebuild-index` to create one from the files there.",
+                    args.output.display()
+                ));
+            }
+
+            let mut catalog = Catalog::open(&args.output, false)?;
+            let selection = Selection {
+                not_verified_for: args.not_verified_for,
+                sample: args.sample.map(|n| n as usize),
+            };
+            let files = verify_library(&state, &mut catalog, selection)?;
+            catalog.close()?;
+            let problems = print_verification(&files);
+            if problems > 0 {
+                return Err(anyhow!("Found {} problems in the library.", problems));
+            }
+        }
     }
 
     Ok(())
@@ -99,55 +484,287 @@ fn _lift_state<T, S>(state: S) -> impl FnOnce(T) -> (T, S) {
     move |i: T| (i, state)
 }
 
-fn import_photos(paths: &[PathBuf], state: &State) -> Vec<Photo> {
-    paths
+/// A group of photos whose destinations have been settled, ready to transfer.
+pub struct ResolvedGroup {
+    pub photos: Vec<Photo>,
+    /// For each photo, the library file or earlier photo in this run it duplicates.
+    pub duplicates: Vec<Option<String>>,
+    /// The stem the group was renamed away from, if it collided.
+    pub collided_with: Option<String>,
+}
+
+/// Discovers and fingerprints every file under `paths` in parallel. Results
+/// come back in discovery order, numbered by `seq`.
+fn scan_photos(paths: &[PathBuf], state: &State) -> Vec<(PhotoPath, Result<Photo>)> {
+    let found = paths
         .iter()
-        .flat_map(find_all_photos)
-        .filter_map(|p| {
-            import_single_photo(&p, state)
-                .inspect_err(|e| eprintln!("{e}"))
-                .ok()
+        .flat_map(|p| find_all_photos(p, &state.photo_matcher))
+        .enumerate()
+        .map(|(i, p)| {
+            let size = std::fs::metadata(&p.input_path).map_or(0, |m| m.len());
+            (PhotoPath { seq: i + 1, ..p }, size)
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>();
+
+    let progress = Progress::start(
+        "Scanning",
+        found.len(),
+        found.iter().map(|(_, size)| size).sum(),
+        state.progress,
+    );
+
+    let legacy_keys = catalog_has_legacy_keys();
+    let scanned = found
+        .into_par_iter()
+        .map(|(p, size)| {
+            let photo = get_photo(&p, legacy_keys, state);
+            progress.inc(size);
+            (p, photo)
+        })
+        .collect();
+
+    progress.finish();
+    scanned
+}
+
+/// Settles the destination of each group in order. This runs on a single
+/// thread so that naming never depends on scheduling; `planned` tracks the
+/// checksums already headed for the library in this run.
+fn resolve_group(
+    mut photos: Vec<Photo>,
+    planned: &mut HashMap<Checksum, String>,
+    state: &State,
+) -> Result<ResolvedGroup> {
+    let mut duplicates = photos
+        .iter()
+        .map(|photo| {
+            Ok(find_in_catalog(photo, state)?.or_else(|| planned.get(&photo.checksum).cloned()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let collided_with = resolve_collision(&mut photos, &mut duplicates, state)?;
+
+    for (photo, duplicate) in photos.iter().zip(&duplicates) {
+        if duplicate.is_none() {
+            planned.insert(photo.checksum.clone(), photo.output_filename.clone());
+        }
+    }
+
+    Ok(ResolvedGroup {
+        photos,
+        duplicates,
+        collided_with,
+    })
+}
+
+fn import_photos(paths: &[PathBuf], state: &State) -> Vec<PlanEntry> {
+    let mut entries = Vec::new();
+    let mut photos = Vec::new();
+
+    for (path, photo) in scan_photos(paths, state) {
+        match photo {
+            Ok(photo) => photos.push(photo),
+            Err(e) => {
+                error!(path = %path.input_path.display(), "{:#}", e);
+                let mut entry = PlanEntry::failed(&path, &e);
+                match quarantine(&path, &e, &state.unsorted_dir) {
+                    Ok(destination) => entry.quarantined_to = Some(destination),
+                    Err(e) => {
+                        error!(path = %path.input_path.display(), "Unable to quarantine: {:#}", e)
+                    }
+                }
+                entries.push(entry);
+            }
+        }
+    }
+
+    let mut planned = HashMap::new();
+    let mut groups = Vec::new();
+
+    for group in group_pairs(photos) {
+        match resolve_group(group.clone(), &mut planned, state) {
+            Ok(resolved) => groups.push(resolved),
+            Err(e) => {
+                error!("{:#}", e);
+                entries.extend(group.iter().map(|photo| {
+                    PlanEntry::for_photo(
+                        photo,
+                        &group,
+                        Outcome::Error {
+                            message: e.to_string(),
+                        },
+                    )
+                }));
+            }
+        }
+    }
+
+    let progress = Progress::start(
+        "Importing",
+        groups.iter().map(|g| g.photos.len()).sum(),
+        groups
+            .iter()
+            .flat_map(|g| &g.photos)
+            .map(|p| p.checksum.size)
+            .sum(),
+        state.progress,
+    );
+
+    entries.par_extend(
+        groups
+            .into_par_iter()
+            .flat_map_iter(|group| import_group(group, state, &progress)),
+    );
+
+    progress.finish();
+
+    entries.sort_by_key(|entry| entry.seq);
+    entries
 }
 
-fn import_single_photo(path: &PhotoPath, state: &State) -> Result<Photo> {
-    get_photo(path, state).and_then(|photo| copy_photo(photo, state))
+/// Copies a resolved group of photos that belong together, such as a RAW+JPEG
+/// pair, to their shared destination stem. The group is imported whole or not
+/// at all: once one photo fails, the rest are not tried and those already
+/// imported are taken back out.
+fn import_group(group: ResolvedGroup, state: &State, progress: &Progress) -> Vec<PlanEntry> {
+    if group.photos.len() > 1 {
+        debug!(
+            "Keeping together: {}",
+            group
+                .photos
+                .iter()
+                .map(|p| p.input_path.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let outcomes = group_outcomes(&group);
+    let mut entries = Vec::new();
+    let mut imported = Vec::new();
+    let mut failed: Option<&Path> = None;
+
+    for (photo, outcome) in group.photos.iter().zip(outcomes) {
+        let mut entry = PlanEntry::for_photo(photo, &group.photos, outcome);
+
+        let result = match (&entry.outcome, failed) {
+            (Outcome::Duplicate { of }, _) => {
+                debug!(
+                    path = %photo.input_path.display(),
+                    existing = %of,
+                    "Skipping duplicate already in library"
+                );
+                Ok(())
+            }
+            (_, Some(failed)) => Err(anyhow!(
+                "Not imported, as {} in the same group failed",
+                failed.display()
+            )),
+            (outcome, None) => {
+                if let Outcome::Collision { existing } = outcome {
+                    debug!(
+                        path = %photo.input_path.display(),
+                        %existing,
+                        renamed = %photo.output_filename,
+                        "Name collision, renaming"
+                    );
+                }
+
+                copy_photo(photo, state).map(|copied| {
+                    entry.bytes_copied = Some(copied);
+                    imported.push(entries.len());
+                })
+            }
+        };
+
+        if let Err(e) = result {
+            error!(path = %photo.input_path.display(), "{:#}", e);
+            entry.outcome = Outcome::TransferFailed {
+                message: format!("{:#}", e),
+            };
+            failed.get_or_insert(&photo.input_path);
+        }
+
+        progress.inc(photo.checksum.size);
+        entries.push(entry);
+    }
+
+    if let Some(failed) = failed {
+        for index in imported {
+            let photo = &group.photos[index];
+            let entry = &mut entries[index];
+            let message = match undo_copy(photo, state) {
+                Ok(()) => format!(
+                    "Taken back out, as {} in the same group failed",
+                    failed.display()
+                ),
+                Err(e) => format!(
+                    "Imported, but {} in the same group failed and undoing the import failed too: {:#}",
+                    failed.display(),
+                    e
+                ),
+            };
+            error!(path = %photo.input_path.display(), "{}", message);
+            entry.outcome = Outcome::TransferFailed { message };
+            entry.bytes_copied = None;
+        }
+    }
+
+    entries
 }
 
-fn find_all_photos<P: AsRef<Path> + Copy>(input_dir: P) -> Vec<PhotoPath> {
+fn find_all_photos<P: AsRef<Path> + Copy>(input_dir: P, matcher: &GlobMatcher) -> Vec<PhotoPath> {
     WalkDir::new(input_dir)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|p| p.ok())
         .map(|d| d.into_path())
-        .filter(|p| GLOB_MATCHER.is_match(p))
-        .map(|p| {
-            println!(
-                "\x1b[36mVerbose (find_all_photos):\x1b[0m Found {}",
-                p.display()
-            );
-            p
-        })
+        .filter(|p| matcher.is_match(p))
+        .inspect(|p| trace!(path = %p.display(), "Found"))
         .map(|p| PhotoPath {
             input_path: p,
             input_dir: input_dir.as_ref().to_path_buf(),
+            seq: 0,
         })
         .collect::<Vec<_>>()
 }
 
-fn get_photo(path: &PhotoPath, state: &State) -> Result<Photo> {
-    let file = File::open(&path.input_path)?;
-    let mut file = BufReader::new(file);
+fn get_photo(path: &PhotoPath, legacy_keys: bool, state: &State) -> Result<Photo> {
+    let (checksum, legacy_key) = if legacy_keys {
+        let (checksum, key) = checksum_file_with_legacy_key(&path.input_path)?;
+        (checksum, Some(key))
+    } else {
+        (checksum_file(&path.input_path)?, None)
+    };
 
-    let checksum = adler32(&mut file)?;
+    let mut exif = state.metadata.read(&path.input_path)?;
 
-    let mut exif = get_exif(&path.input_path)?;
+    let recorded_date = exif.date_time_original.or(exif.create_date);
+    let corrected = state.clock_corrections.correct(&mut exif);
+    if corrected {
+        debug!(
+            path = %path.input_path.display(),
+            recorded = %recorded_date.map(|d| d.to_string()).unwrap_or_default(),
+            corrected = %exif
+                .date_time_original
+                .or(exif.create_date)
+                .map(|d| d.to_string())
+                .unwrap_or_default(),
+            "Correcting camera clock"
+        );
+    }
 
-    let extension = path
-        .input_path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
+    merge_sidecar(&path.input_path, &mut exif)?;
+
+    let date_source = date_photo(&mut exif, &path.input_path, state);
+    if let Some(source) = date_source.filter(|s| *s != DateSource::Exif) {
+        debug!(
+            path = %path.input_path.display(),
+            source = source.name(),
+            date = %exif.fallback_date.map(|d| d.to_string()).unwrap_or_default(),
+            "Using fallback date"
+        );
+    }
 
     if state.album_from_filename
         && path.input_path.ancestors().count() - 1 > path.input_dir.ancestors().count()
@@ -159,8 +776,7 @@ fn get_photo(path: &PhotoPath, state: &State) -> Result<Photo> {
             .map(|s| s.to_string_lossy().to_string());
     };
 
-    let filename =
-        generate_filename(&exif).map(|file_prefix| format!("{}.{}", file_prefix, extension))?;
+    let (filename, kind) = destination(&exif, &path.input_path, &checksum, path.seq, state)?;
 
     Ok(Photo {
         input_path: path.input_path.to_path_buf(),
@@ -170,23 +786,104 @@ fn get_photo(path: &PhotoPath, state: &State) -> Result<Photo> {
             .file_name()
             .map(|f| f.to_string_lossy().into_owned()),
         output_filename: filename,
+        checksum,
+        kind,
+        seq: path.seq,
+        corrected_dates: if corrected && state.write_corrected_dates {
+            [
+                (Tag::DateTimeOriginal, exif.date_time_original),
+                (Tag::CreateDate, exif.create_date),
+            ]
+            .into_iter()
+            .filter_map(|(tag, date)| Some((tag, date?)))
+            .collect()
+        } else {
+            Vec::new()
+        },
+        date_source,
+        legacy_key,
         exif,
-        _checksum: checksum,
     })
 }
 
+/// Settles the capture time of the file at `path` from the first of the
+/// configured date sources that has one, in the display zone. Returns the
+/// source used; without one, `exif` is left without a capture time.
+fn date_photo(exif: &mut Exif, path: &Path, state: &State) -> Option<DateSource> {
+    let source = choose_date_source(exif, path, &state.date_sources);
+    exif.capture_time = source.and_then(|_| state.time_zones.resolve(exif));
+    source
+}
+
+/// Where a file with metadata `exif` goes, relative to the library root.
+/// `input_path` supplies the extension and the `{orig_*}` placeholders.
+fn destination(
+    exif: &Exif,
+    input_path: &Path,
+    checksum: &Checksum,
+    seq: usize,
+    state: &State,
+) -> Result<(String, MediaKind)> {
+    let extension = input_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+
+    let file_prefix = match &state.template {
+        Some(template) => template.render(&TemplateContext {
+            exif,
+            input_path,
+            checksum,
+            seq,
+            time_zones: &state.time_zones,
+        })?,
+        None => generate_filename(exif)?,
+    };
+
+    let kind = if state.video_matcher.is_match(input_path) {
+        MediaKind::Video
+    } else {
+        MediaKind::Photo
+    };
+
+    let filename = format!(
+        "{}{}.{}",
+        layout_dir(kind, state.video_layout),
+        file_prefix,
+        extension
+    );
+
+    Ok((filename, kind))
+}
+
+/// The directory a file of `kind` goes under ahead of the naming template.
+fn layout_dir(kind: MediaKind, layout: VideoLayout) -> &'static str {
+    match (kind, layout) {
+        (MediaKind::Video, VideoLayout::Separate) => "videos/",
+        _ => "",
+    }
+}
+
 fn generate_camera(exif: &Exif) -> Option<String> {
-    match (&exif.make, &exif.model) {
+    match (exif.camera_make(), exif.camera_model()) {
         (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
         _ => None,
     }
 }
 
+#[derive(Debug)]
+pub struct MissingDateError;
+
+impl std::fmt::Display for MissingDateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EXIF data is missing DateTime")
+    }
+}
+
+impl std::error::Error for MissingDateError {}
+
 fn generate_filename(exif: &Exif) -> Result<String> {
-    let date = exif
-        .date_time_original
-        .or(exif.create_date)
-        .ok_or_else(|| anyhow!("EXIF data is missing DateTime"))?;
+    let date = exif.capture_date().ok_or(MissingDateError)?;
 
     let mut s = match &exif.album {
         Some(i) => format!("albums/{}", i),
@@ -203,7 +900,138 @@ fn generate_filename(exif: &Exif) -> Result<String> {
     Ok(s)
 }
 
-fn copy_photo(photo: Photo, state: &State) -> Result<Photo> {
+/// Splits `filename` into the path without its extension and the extension.
+fn split_extension(filename: &str) -> (String, String) {
+    let path = Path::new(filename);
+
+    match path.extension() {
+        Some(ext) => (
+            path.with_extension("").to_string_lossy().into_owned(),
+            ext.to_string_lossy().into_owned(),
+        ),
+        None => (filename.to_string(), String::new()),
+    }
+}
+
+/// Returns the source checksum of whatever already occupies `filename` in
+/// the library, whether claimed earlier in this run or present on disk. Files
+/// on disk may have had tags written into them since, so the catalog is asked
+/// what they were imported from; only files it doesn't know are hashed.
+fn occupant_checksum(filename: &str, state: &State) -> Result<Option<Checksum>> {
+    let claimed = state.claimed.lock().map_err(|e| anyhow!(e.to_string()))?;
+
+    if let Some(checksum) = claimed.get(filename) {
+        return Ok(Some(checksum.clone()));
+    }
+
+    let path = state.output_dir.join(filename);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let catalog = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?;
+    let source = catalog
+        .as_ref()
+        .ok_or_else(|| anyhow!("Unable to open catalog"))?
+        .source_of(filename)?;
+
+    match source {
+        Some(source) => Ok(Some(source)),
+        None => Ok(Some(checksum_file(path)?)),
+    }
+}
+
+/// Claims free destinations for a group of photos that share a stem. When the
+/// generated name is taken by a different file, the sub-second capture time is
+/// tried first and then a numeric suffix; a stem is only used if it is free
+/// for every member. `duplicates` holds the library file each member is known
+/// to duplicate, and gains an entry for every member whose destination turns
+/// out to hold identical content. Members already in the library pull the rest
+/// of the group next to them. Each member keeps its own layout directory, so a
+/// Live Photo's video still goes under videos/ with `--videos separate`.
+/// Returns the stem the group was renamed away from if it collided.
+fn resolve_collision(
+    group: &mut [Photo],
+    duplicates: &mut [Option<String>],
+    state: &State,
+) -> Result<Option<String>> {
+    for (photo, duplicate) in group.iter_mut().zip(duplicates.iter()) {
+        if let Some(existing) = duplicate {
+            photo.output_filename = existing.clone();
+        }
+    }
+
+    if duplicates.iter().all(Option::is_some) {
+        return Ok(None);
+    }
+
+    let (leader, name) = group
+        .iter()
+        .zip(duplicates.iter())
+        .find_map(|(photo, duplicate)| Some((photo, duplicate.as_ref()?)))
+        .unwrap_or((&group[0], &group[0].output_filename));
+    let (base, _) = split_extension(name);
+    let base = base
+        .strip_prefix(layout_dir(leader.kind, state.video_layout))
+        .unwrap_or(&base)
+        .to_string();
+
+    let candidates = std::iter::once(base.clone())
+        .chain(
+            group[0]
+                .exif
+                .sub_sec_time_original
+                .as_ref()
+                .map(|subsec| format!("{}-{}", base, subsec.trim())),
+        )
+        .chain((1..).map(|n| format!("{}_{}", base, n)));
+
+    'candidates: for stem in candidates {
+        let mut names = Vec::with_capacity(group.len());
+
+        for (photo, duplicate) in group.iter().zip(duplicates.iter()) {
+            if duplicate.is_some() {
+                names.push(None);
+                continue;
+            }
+
+            let (_, extension) = split_extension(&photo.output_filename);
+            let name = format!(
+                "{}{}.{}",
+                layout_dir(photo.kind, state.video_layout),
+                stem,
+                extension
+            );
+
+            match occupant_checksum(&name, state)? {
+                Some(checksum) if checksum != photo.checksum => continue 'candidates,
+                occupant => names.push(Some((name, occupant.is_some()))),
+            }
+        }
+
+        let mut claimed = state.claimed.lock().map_err(|e| anyhow!(e.to_string()))?;
+
+        for ((photo, duplicate), name) in group.iter_mut().zip(duplicates.iter_mut()).zip(names) {
+            if let Some((name, identical)) = name {
+                if identical {
+                    *duplicate = Some(name.clone());
+                } else {
+                    claimed.insert(name.clone(), photo.checksum.clone());
+                }
+                photo.output_filename = name;
+            }
+        }
+
+        return Ok(if stem != base { Some(base) } else { None });
+    }
+
+    unreachable!("numeric suffixes are unbounded")
+}
+
+/// Transfers `photo` into the library and tags it, returning the number of
+/// bytes copied.
+fn copy_photo(photo: &Photo, state: &State) -> Result<u64> {
     let output_filename = format!(
         "{}/{}",
         state.output_dir.to_string_lossy(),
@@ -212,40 +1040,195 @@ fn copy_photo(photo: Photo, state: &State) -> Result<Photo> {
     let output_path = Path::new(&output_filename);
 
     if let Ok(_file) = File::open(output_path) {
-        println!(
-            "\x1b[36mVerbose (copy_photos\x1b[35;1m {}\x1b[36m):\x1b[0m Canceling copy: output file already exists",
-            &photo.input_path.to_string_lossy()
+        return Err(anyhow!(
+            "Canceling copy of {}: output file {} already exists",
+            photo.input_path.to_string_lossy(),
+            output_path.to_string_lossy()
+        ));
+    }
+
+    if let Some(output_dirs) = output_path.parent() {
+        trace!(
+            path = %photo.input_path.display(),
+            directory = %output_dirs.display(),
+            "Creating output directory"
         );
-    } else {
-        if let Some(output_dirs) = output_path.parent() {
-            println!(
-                "\x1b[36mVerbose (copy_photos\x1b[35;1m {}\x1b[36m):\x1b[0m Creating output directory: \x1b[35;1m{}\x1b[0m",
-                &photo.input_path.to_string_lossy(),
-                output_dirs.to_string_lossy()
-            );
-            std::fs::create_dir_all(output_dirs)?
+        std::fs::create_dir_all(output_dirs)?
+    }
+
+    debug!(
+        path = %photo.input_path.display(),
+        destination = %output_path.display(),
+        "{} photo",
+        match state.transfer_mode {
+            TransferMode::Copy => "Copying",
+            TransferMode::Move => "Moving",
+            TransferMode::Link(_) => "Linking",
         }
+    );
+    let copied = transfer(
+        &photo.input_path,
+        output_path,
+        &photo.checksum,
+        state.transfer_mode,
+    )?;
 
-        println!(
-            "\x1b[36mVerbose (copy_photos\x1b[35;1m {}\x1b[36m):\x1b[0m Copying photo to: \x1b[35;1m{}\x1b[0m",
-            &photo.input_path.to_string_lossy(),
-            output_path.to_string_lossy()
-        );
-        copy(photo.input_path.as_path(), output_path)?;
-        write_exif(output_path, &photo)?;
-        write_photohash(&photo)?;
+    if let Err(e) = tag_and_record(photo, output_path, state) {
+        if let Err(undo) = undo_copy(photo, state) {
+            error!(path = %photo.input_path.display(), "Unable to undo import: {:#}", undo);
+        }
+        return Err(e);
     }
 
-    Ok(photo)
+    Ok(copied)
+}
+
+fn tag_and_record(photo: &Photo, output_path: &Path, state: &State) -> Result<()> {
+    let tags = photo_tags(photo);
+
+    // Files no backend can write into get a sidecar, as with `--xmp-sidecar`.
+    let in_place = !state.xmp_sidecar
+        && match state.metadata.write(output_path, &tags) {
+            Ok(()) => true,
+            Err(e) if e.is::<UnwritableError>() => {
+                debug!(path = %output_path.display(), "{:#}; using a sidecar", e);
+                false
+            }
+            Err(e) => return Err(e),
+        };
+
+    // Tags written into the copy change its bytes, so it is fingerprinted
+    // again for later verification.
+    let file_checksum = if in_place {
+        checksum_file(output_path)?
+    } else {
+        write_sidecar(output_path, &tags)?;
+        photo.checksum.clone()
+    };
+    record_in_catalog(photo, file_checksum)
 }
 
-fn write_photohash(photo: &Photo) -> Result<()> {
-    let db_mutex = PHOTOHASH_DB
-        .get()
-        .ok_or_else(|| anyhow!("Unable to open photohash db"))?;
+/// Takes a photo transferred by [`copy_photo`] back out of the library: its
+/// file, sidecar and catalog entry are removed, and a moved file is put back
+/// where it came from. Tags already written into a moved file stay with it.
+fn undo_copy(photo: &Photo, state: &State) -> Result<()> {
+    let output_path = state.output_dir.join(&photo.output_filename);
+
+    let sidecar = sidecar_path(&output_path);
+    if sidecar.exists() {
+        std::fs::remove_file(&sidecar)?;
+    }
+
+    match state.transfer_mode {
+        TransferMode::Move => match std::fs::rename(&output_path, &photo.input_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                std::fs::copy(&output_path, &photo.input_path)?;
+                std::fs::remove_file(&output_path)?;
+            }
+            Err(e) => return Err(e.into()),
+        },
+        TransferMode::Copy | TransferMode::Link(_) => std::fs::remove_file(&output_path)?,
+    }
 
-    let mut db = db_mutex.lock().map_err(|e| anyhow!(e.to_string()))?;
+    let catalog = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?;
+    catalog
+        .as_ref()
+        .ok_or_else(|| anyhow!("Unable to open catalog"))?
+        .forget(&photo.output_filename)
+}
 
-    db.set(photo._checksum.to_string().as_str(), &photo.output_filename)?;
+fn set_catalog(catalog: Catalog) -> Result<()> {
+    let mut current = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?;
+    if current.is_some() {
+        return Err(anyhow!("Catalog already initialized."));
+    }
+    *current = Some(catalog);
     Ok(())
 }
+
+/// Closes the catalog opened with [`set_catalog`], so that it is left
+/// checkpointed rather than with its write-ahead log alongside.
+fn close_catalog() -> Result<()> {
+    let catalog = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?.take();
+    match catalog {
+        Some(catalog) => catalog.close(),
+        None => Ok(()),
+    }
+}
+
+fn record_in_catalog(photo: &Photo, file_checksum: Checksum) -> Result<()> {
+    let catalog = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?;
+    let catalog = catalog
+        .as_ref()
+        .ok_or_else(|| anyhow!("Unable to open catalog"))?;
+
+    catalog.record(&CatalogEntry::for_photo(photo, file_checksum))
+}
+
+/// Whether the catalog has entries migrated from `photohash.db` that can only
+/// be found by their adler32 checksum.
+fn catalog_has_legacy_keys() -> bool {
+    CATALOG
+        .lock()
+        .ok()
+        .and_then(|catalog| catalog.as_ref().map(|c| c.has_legacy_keys()))
+        .unwrap_or(false)
+}
+
+/// Looks up the photo's checksum in the catalog and returns the library file
+/// it was previously imported as, if any. Entries migrated from an adler32
+/// `photohash.db` are looked up by that checksum instead, but only count once
+/// the library file is confirmed to be a copy of the photo.
+fn find_in_catalog(photo: &Photo, state: &State) -> Result<Option<String>> {
+    let candidate = {
+        let catalog = CATALOG.lock().map_err(|e| anyhow!(e.to_string()))?;
+        let catalog = catalog
+            .as_ref()
+            .ok_or_else(|| anyhow!("Unable to open catalog"))?;
+
+        if let Some(found) = catalog.find(&photo.checksum)? {
+            return Ok(Some(found));
+        }
+        match photo.legacy_key {
+            Some(key) => catalog.find_legacy(key)?,
+            None => None,
+        }
+    };
+
+    Ok(candidate.filter(|path| {
+        let confirmed = is_legacy_copy(photo, path, state);
+        if !confirmed {
+            warn!(
+                path = %photo.input_path.display(),
+                candidate = %path,
+                "Shares an old adler32 checksum with a library file that does not look like a copy; importing it"
+            );
+        }
+        confirmed
+    }))
+}
+
+/// Whether the library file at `path` is a copy of `photo`. Adler32 collides
+/// far too easily for a matching checksum to be enough, so the copy must also
+/// have the photo's capture date and camera, and its original file name if it
+/// recorded one.
+fn is_legacy_copy(photo: &Photo, path: &str, state: &State) -> bool {
+    let path = state.output_dir.join(path);
+    let Ok(mut copy) = state.metadata.read(&path) else {
+        return false;
+    };
+    if merge_sidecar(&path, &mut copy).is_err() {
+        return false;
+    }
+
+    let date = |exif: &Exif| exif.date_time_original.or(exif.create_date);
+    date(&copy).is_some()
+        && date(&copy) == date(&photo.exif)
+        && copy.camera_make() == photo.exif.camera_make()
+        && copy.camera_model() == photo.exif.camera_model()
+        && copy
+            .original_filename
+            .as_ref()
+            .is_none_or(|name| Some(name) == photo.original_filename.as_ref())
+}
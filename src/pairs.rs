@@ -0,0 +1,194 @@
+use crate::{MediaKind, Photo};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// RAW+JPEG members are only paired when their capture times are at most this
+/// many seconds apart.
+const MAX_CAPTURE_DELTA_SECONDS: i64 = 2;
+
+fn stem_key(photo: &Photo) -> Option<(PathBuf, String)> {
+    Some((
+        photo.input_path.parent()?.to_path_buf(),
+        photo
+            .input_path
+            .file_stem()?
+            .to_string_lossy()
+            .to_lowercase(),
+    ))
+}
+
+/// The file's format, by extension, with aliases such as jpeg for jpg folded
+/// together.
+fn extension(photo: &Photo) -> String {
+    let extension = photo
+        .input_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpeg" => "jpg".to_string(),
+        "tiff" => "tif".to_string(),
+        "heif" => "heic".to_string(),
+        _ => extension,
+    }
+}
+
+fn captured_together(a: &Photo, b: &Photo) -> bool {
+    match (a.exif.capture_date(), b.exif.capture_date()) {
+        (Some(a), Some(b)) => (a - b).num_seconds().abs() <= MAX_CAPTURE_DELTA_SECONDS,
+        _ => true,
+    }
+}
+
+/// Groups files that belong to the same shot: RAW+JPEG pairs sharing a stem
+/// and capture time, and Live Photos whose image and video share a
+/// `ContentIdentifier`. Groups keep discovery order, and the first photo of a
+/// group (videos last) is the one whose destination the others follow.
+pub fn group_pairs(photos: Vec<Photo>) -> Vec<Vec<Photo>> {
+    let mut groups: Vec<Vec<Photo>> = Vec::new();
+    let mut by_content_id: HashMap<String, usize> = HashMap::new();
+    let mut by_stem: HashMap<(PathBuf, String), usize> = HashMap::new();
+
+    for photo in photos {
+        let content_id = photo.exif.content_identifier().cloned();
+        let stem = stem_key(&photo);
+
+        let existing = content_id
+            .as_ref()
+            .and_then(|id| by_content_id.get(id))
+            .or_else(|| stem.as_ref().and_then(|s| by_stem.get(s)))
+            .copied()
+            .filter(|&i| {
+                let group = &groups[i];
+                group.iter().all(|p| extension(p) != extension(&photo))
+                    && (content_id.is_some() || captured_together(&group[0], &photo))
+            });
+
+        let index = match existing {
+            Some(i) => {
+                groups[i].push(photo);
+                i
+            }
+            None => {
+                groups.push(vec![photo]);
+                groups.len() - 1
+            }
+        };
+
+        if let Some(id) = content_id {
+            by_content_id.entry(id).or_insert(index);
+        }
+        if let Some(stem) = stem {
+            by_stem.entry(stem).or_insert(index);
+        }
+    }
+
+    for group in groups.iter_mut() {
+        group.sort_by_key(|p| p.kind == MediaKind::Video);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Checksum;
+    use crate::exif::Exif;
+    use crate::timezone::TimeZones;
+    use chrono::NaiveDate;
+
+    fn photo(path: &str, second: u32, content_id: Option<&str>) -> Photo {
+        let mut exif = Exif {
+            date_time_original: NaiveDate::from_ymd_opt(2023, 1, 4)
+                .and_then(|d| d.and_hms_opt(10, 15, second)),
+            makernotes_content_identifier: content_id.map(str::to_string),
+            ..Exif::default()
+        };
+        exif.capture_time = TimeZones::default().resolve(&exif);
+
+        Photo {
+            input_path: PathBuf::from(path),
+            original_filename: None,
+            output_filename: String::new(),
+            exif,
+            checksum: Checksum {
+                blake3: String::new(),
+                size: 0,
+            },
+            kind: if path.ends_with(".mov") {
+                MediaKind::Video
+            } else {
+                MediaKind::Photo
+            },
+            seq: 0,
+            corrected_dates: Vec::new(),
+            date_source: None,
+            legacy_key: None,
+        }
+    }
+
+    fn paths(groups: &[Vec<Photo>]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|g| g.iter().map(|p| p.input_path.to_str().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn pairs_raw_and_jpeg_sharing_a_stem_and_time() {
+        let groups = group_pairs(vec![
+            photo("a/IMG_0001.JPG", 0, None),
+            photo("a/img_0001.orf", 1, None),
+            photo("b/IMG_0001.orf", 0, None),
+        ]);
+        assert_eq!(
+            paths(&groups),
+            [
+                vec!["a/IMG_0001.JPG", "a/img_0001.orf"],
+                vec!["b/IMG_0001.orf"]
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_apart_what_was_taken_apart_or_is_the_same_format() {
+        let groups = group_pairs(vec![
+            photo("IMG_0001.jpg", 0, None),
+            photo("IMG_0001.orf", 3, None),
+            photo("IMG_0001.jpeg", 0, None),
+            photo("IMG_0001.JPG", 0, None),
+            photo("IMG_0002.tif", 0, None),
+            photo("IMG_0002.TIFF", 0, None),
+            photo("IMG_0003.heic", 0, None),
+            photo("IMG_0003.heif", 0, None),
+        ]);
+        assert_eq!(
+            paths(&groups),
+            [
+                vec!["IMG_0001.jpg"],
+                vec!["IMG_0001.orf"],
+                vec!["IMG_0001.jpeg"],
+                vec!["IMG_0001.JPG"],
+                vec!["IMG_0002.tif"],
+                vec!["IMG_0002.TIFF"],
+                vec!["IMG_0003.heic"],
+                vec!["IMG_0003.heif"]
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_live_photos_by_content_identifier_with_the_video_last() {
+        let groups = group_pairs(vec![
+            photo("IMG_0002.mov", 30, Some("live")),
+            photo("IMG_0001.heic", 0, Some("live")),
+            photo("IMG_0003.heic", 0, Some("other")),
+        ]);
+        assert_eq!(
+            paths(&groups),
+            [vec!["IMG_0001.heic", "IMG_0002.mov"], vec!["IMG_0003.heic"]]
+        );
+    }
+}
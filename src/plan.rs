@@ -0,0 +1,327 @@
+use crate::checksum::Checksum;
+use crate::datesource::DateSource;
+use crate::logging::use_color;
+use crate::pairs::group_pairs;
+use crate::quarantine::QuarantineReason;
+use crate::{
+    resolve_group, scan_photos, split_extension, MediaKind, Photo, PhotoPath, ResolvedGroup, State,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Import,
+    /// Imported under a different name because `existing` was already taken.
+    Collision {
+        existing: String,
+    },
+    Duplicate {
+        of: String,
+    },
+    MissingDate,
+    UnsupportedFormat {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    /// Named successfully, but copying or tagging the file failed.
+    TransferFailed {
+        message: String,
+    },
+}
+
+impl Outcome {
+    /// Outcome of a file that could not be scanned.
+    pub fn from_error(error: &anyhow::Error) -> Outcome {
+        match QuarantineReason::of(error) {
+            QuarantineReason::MissingDate => Outcome::MissingDate,
+            QuarantineReason::UnsupportedFormat => Outcome::UnsupportedFormat {
+                message: error.to_string(),
+            },
+            QuarantineReason::MetadataError => Outcome::Error {
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// What happens, or would happen, to a single file. Used both for `test`
+/// plans and for the report of an import.
+#[derive(Serialize, Debug, Clone)]
+pub struct PlanEntry {
+    /// Position in discovery order.
+    #[serde(skip)]
+    pub seq: usize,
+    pub source: PathBuf,
+    pub kind: Option<MediaKind>,
+    pub destination: Option<String>,
+    pub checksum: Option<Checksum>,
+    /// Where the capture date came from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_source: Option<DateSource>,
+    /// Other files kept together with this one, e.g. the RAW of a RAW+JPEG pair.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paired_with: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    /// Bytes written to the library; links and renames copy nothing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_copied: Option<u64>,
+    /// Where the file was copied to after failing to import.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantined_to: Option<PathBuf>,
+}
+
+impl PlanEntry {
+    pub fn failed(path: &PhotoPath, error: &anyhow::Error) -> PlanEntry {
+        PlanEntry {
+            seq: path.seq,
+            source: path.input_path.clone(),
+            kind: None,
+            destination: None,
+            checksum: None,
+            date_source: None,
+            paired_with: Vec::new(),
+            outcome: Outcome::from_error(error),
+            bytes_copied: None,
+            quarantined_to: None,
+        }
+    }
+
+    pub fn for_photo(photo: &Photo, group: &[Photo], outcome: Outcome) -> PlanEntry {
+        PlanEntry {
+            seq: photo.seq,
+            source: photo.input_path.clone(),
+            kind: Some(photo.kind),
+            destination: Some(photo.output_filename.clone()),
+            checksum: Some(photo.checksum.clone()),
+            date_source: photo.date_source,
+            paired_with: group
+                .iter()
+                .filter(|p| p.seq != photo.seq)
+                .map(|p| p.input_path.clone())
+                .collect(),
+            outcome,
+            bytes_copied: None,
+            quarantined_to: None,
+        }
+    }
+}
+
+/// The outcome of importing each member of a resolved group.
+pub fn group_outcomes(group: &ResolvedGroup) -> Vec<Outcome> {
+    group
+        .duplicates
+        .iter()
+        .zip(group.photos.iter())
+        .map(
+            |(duplicate, photo)| match (duplicate, &group.collided_with) {
+                (Some(of), _) => Outcome::Duplicate { of: of.clone() },
+                // Renaming only changes the file name, not the directory.
+                (None, Some(base)) => Outcome::Collision {
+                    existing: Path::new(&photo.output_filename)
+                        .with_file_name(format!(
+                            "{}.{}",
+                            Path::new(base)
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy(),
+                            split_extension(&photo.output_filename).1
+                        ))
+                        .to_string_lossy()
+                        .into_owned(),
+                },
+                (None, None) => Outcome::Import,
+            },
+        )
+        .collect()
+}
+
+/// Runs discovery, fingerprinting and naming for every photo without copying
+/// anything, writing metadata or updating the catalog.
+pub fn plan_photos(paths: &[PathBuf], state: &State) -> Vec<PlanEntry> {
+    let mut entries = Vec::new();
+    let mut photos = Vec::new();
+
+    for (path, photo) in scan_photos(paths, state) {
+        match photo {
+            Ok(photo) => photos.push(photo),
+            Err(e) => entries.push(PlanEntry::failed(&path, &e)),
+        }
+    }
+
+    let mut planned_checksums: HashMap<Checksum, String> = HashMap::new();
+
+    for group in group_pairs(photos) {
+        match resolve_group(group.clone(), &mut planned_checksums, state) {
+            Ok(resolved) => {
+                entries.extend(
+                    resolved.photos.iter().zip(group_outcomes(&resolved)).map(
+                        |(photo, outcome)| PlanEntry::for_photo(photo, &resolved.photos, outcome),
+                    ),
+                )
+            }
+            Err(e) => entries.extend(group.iter().map(|photo| {
+                PlanEntry::for_photo(
+                    photo,
+                    &group,
+                    Outcome::Error {
+                        message: e.to_string(),
+                    },
+                )
+            })),
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.seq);
+    entries
+}
+
+/// Counts of each outcome across a plan or import.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub found: usize,
+    pub imported: usize,
+    pub collisions: usize,
+    pub duplicates: usize,
+    pub missing_dates: usize,
+    pub unsupported_formats: usize,
+    pub errors: usize,
+    pub failed_transfers: usize,
+    pub bytes_copied: u64,
+}
+
+impl Summary {
+    pub fn of(entries: &[PlanEntry]) -> Summary {
+        let mut summary = Summary {
+            found: entries.len(),
+            ..Default::default()
+        };
+
+        for entry in entries {
+            match entry.outcome {
+                Outcome::Import => summary.imported += 1,
+                Outcome::Collision { .. } => {
+                    summary.imported += 1;
+                    summary.collisions += 1;
+                }
+                Outcome::Duplicate { .. } => summary.duplicates += 1,
+                Outcome::MissingDate => summary.missing_dates += 1,
+                Outcome::UnsupportedFormat { .. } => summary.unsupported_formats += 1,
+                Outcome::Error { .. } => summary.errors += 1,
+                Outcome::TransferFailed { .. } => summary.failed_transfers += 1,
+            }
+            summary.bytes_copied += entry.bytes_copied.unwrap_or(0);
+        }
+
+        summary
+    }
+
+    pub fn failed(&self) -> usize {
+        self.missing_dates + self.unsupported_formats + self.errors + self.failed_transfers
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `12.3 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn print_plan(plan: &[PlanEntry]) {
+    let color = use_color(&std::io::stdout());
+    let paint = |code: &str, text: String| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text
+        }
+    };
+
+    for entry in plan {
+        let source = entry.source.to_string_lossy();
+        let destination = entry.destination.as_deref().unwrap_or("");
+
+        match &entry.outcome {
+            Outcome::Import => println!("{} -> {}", source, destination),
+            Outcome::Collision { existing } => println!(
+                "{} -> {} {}",
+                source,
+                destination,
+                paint("33", format!("(renamed, {} is taken)", existing))
+            ),
+            Outcome::Duplicate { of } => {
+                println!(
+                    "{} {}",
+                    source,
+                    paint("33", format!("(duplicate of {})", of))
+                )
+            }
+            Outcome::MissingDate => {
+                println!("{} {}", source, paint("31", "(missing date)".to_string()))
+            }
+            Outcome::UnsupportedFormat { .. } => {
+                println!(
+                    "{} {}",
+                    source,
+                    paint("31", "(unsupported format)".to_string())
+                )
+            }
+            Outcome::Error { message } | Outcome::TransferFailed { message } => {
+                println!(
+                    "{} {}",
+                    source,
+                    paint("31", format!("(error: {})", message))
+                )
+            }
+        }
+    }
+
+    let summary = Summary::of(plan);
+    println!(
+        "\nPlan: {} to import, {} collisions, {} duplicates, {} missing dates, {} unsupported formats, {} errors",
+        summary.imported - summary.collisions,
+        summary.collisions,
+        summary.duplicates,
+        summary.missing_dates,
+        summary.unsupported_formats,
+        summary.errors
+    );
+}
+
+pub fn print_summary(entries: &[PlanEntry]) {
+    let summary = Summary::of(entries);
+
+    println!();
+    println!("Found:       {}", summary.found);
+    println!(
+        "Imported:    {} ({} renamed after a name collision)",
+        summary.imported, summary.collisions
+    );
+    println!("Duplicates:  {}", summary.duplicates);
+    println!(
+        "Failed:      {} ({} missing dates, {} unsupported formats, {} metadata errors, {} failed transfers)",
+        summary.failed(),
+        summary.missing_dates,
+        summary.unsupported_formats,
+        summary.errors,
+        summary.failed_transfers
+    );
+    println!("Copied:      {}", format_bytes(summary.bytes_copied));
+}
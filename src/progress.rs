@@ -0,0 +1,178 @@
+use indicatif::{BinaryBytes, HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use once_cell::sync::Lazy;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// How often a progress line is printed when stderr is not a terminal.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The bar currently on screen, so that log lines can be written around it.
+static ACTIVE: Lazy<Mutex<Option<ProgressBar>>> = Lazy::new(|| Mutex::new(None));
+
+/// Runs `f` with the progress bar, if any, hidden from the terminal.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    let bar = ACTIVE.lock().ok().and_then(|active| active.clone());
+
+    match bar {
+        Some(bar) => bar.suspend(f),
+        None => f(),
+    }
+}
+
+/// How progress is shown. It goes to stderr with the log, leaving stdout to
+/// plans and summaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressOutput {
+    Hidden,
+    /// A bar on a terminal, otherwise periodic lines.
+    Text,
+    /// Periodic log events, for JSON logs.
+    Log,
+}
+
+enum Display {
+    Hidden,
+    /// A live bar on a terminal.
+    Bar(ProgressBar),
+    /// Periodic lines for logs and pipes, holding when the last one went out.
+    Plain(Mutex<Instant>),
+    /// Periodic log events, holding when the last one went out.
+    Log(Mutex<Instant>),
+}
+
+/// Files and bytes processed during one phase of a run, such as scanning or
+/// importing.
+pub struct Progress {
+    phase: &'static str,
+    total_files: usize,
+    total_bytes: u64,
+    files: AtomicUsize,
+    bytes: AtomicU64,
+    started: Instant,
+    display: Display,
+}
+
+impl Progress {
+    pub fn start(
+        phase: &'static str,
+        total_files: usize,
+        total_bytes: u64,
+        output: ProgressOutput,
+    ) -> Progress {
+        let display = match output {
+            ProgressOutput::Hidden => Display::Hidden,
+            ProgressOutput::Log => Display::Log(Mutex::new(Instant::now())),
+            ProgressOutput::Text if std::io::stderr().is_terminal() => {
+                let bar = ProgressBar::with_draw_target(Some(total_bytes), ProgressDrawTarget::stderr())
+                    .with_style(
+                        ProgressStyle::with_template(
+                            "{prefix:>9} [{elapsed_precise}] {wide_bar} {binary_bytes}/{binary_total_bytes} {msg} ({binary_bytes_per_sec}, ETA {eta})",
+                        )
+                        .expect("progress template is valid"),
+                    )
+                    .with_prefix(phase);
+                bar.set_message(format!("0/{} files", total_files));
+                bar.enable_steady_tick(Duration::from_millis(200));
+
+                if let Ok(mut active) = ACTIVE.lock() {
+                    *active = Some(bar.clone());
+                }
+
+                Display::Bar(bar)
+            }
+            ProgressOutput::Text => Display::Plain(Mutex::new(Instant::now())),
+        };
+
+        Progress {
+            phase,
+            total_files,
+            total_bytes,
+            files: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            started: Instant::now(),
+            display,
+        }
+    }
+
+    /// Records one more file of `bytes` bytes as processed.
+    pub fn inc(&self, bytes: u64) {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+
+        match &self.display {
+            Display::Hidden => {}
+            Display::Bar(bar) => {
+                bar.inc(bytes);
+                bar.set_message(format!("{}/{} files", files, self.total_files));
+            }
+            Display::Plain(last) | Display::Log(last) => {
+                let Ok(mut last) = last.lock() else {
+                    return;
+                };
+                if last.elapsed() >= PLAIN_INTERVAL {
+                    *last = Instant::now();
+                    self.print();
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        match &self.display {
+            Display::Hidden => {}
+            Display::Bar(bar) => {
+                bar.finish_and_clear();
+                if let Ok(mut active) = ACTIVE.lock() {
+                    *active = None;
+                }
+                eprintln!("{}", self.line());
+            }
+            Display::Plain(_) | Display::Log(_) => self.print(),
+        }
+    }
+
+    fn print(&self) {
+        match &self.display {
+            Display::Log(_) => info!(
+                phase = self.phase,
+                files = self.files.load(Ordering::Relaxed),
+                total_files = self.total_files,
+                bytes = self.bytes.load(Ordering::Relaxed),
+                total_bytes = self.total_bytes,
+                "{}",
+                self.line()
+            ),
+            _ => eprintln!("{}", self.line()),
+        }
+    }
+
+    fn line(&self) -> String {
+        let files = self.files.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed();
+        let rate = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+
+        let mut line = format!(
+            "{}: {}/{} files, {}/{} ({}/s)",
+            self.phase,
+            files,
+            self.total_files,
+            BinaryBytes(bytes),
+            BinaryBytes(self.total_bytes),
+            BinaryBytes(rate as u64)
+        );
+
+        if files < self.total_files && rate > 0.0 {
+            let remaining = self.total_bytes.saturating_sub(bytes) as f64 / rate;
+            line.push_str(&format!(
+                ", ETA {}",
+                HumanDuration(Duration::from_secs_f64(remaining))
+            ));
+        }
+
+        line
+    }
+}
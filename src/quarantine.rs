@@ -0,0 +1,105 @@
+use crate::checksum::checksum_file;
+use crate::exif::UnsupportedFormatError;
+use crate::{MissingDateError, PhotoPath};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantineReason {
+    MissingDate,
+    UnsupportedFormat,
+    MetadataError,
+}
+
+impl QuarantineReason {
+    pub fn of(error: &anyhow::Error) -> QuarantineReason {
+        if error.is::<MissingDateError>() {
+            QuarantineReason::MissingDate
+        } else if error.is::<UnsupportedFormatError>() {
+            QuarantineReason::UnsupportedFormat
+        } else {
+            QuarantineReason::MetadataError
+        }
+    }
+}
+
+/// Contents of the `<file>.reason.json` written next to each quarantined file.
+#[derive(Serialize, Debug)]
+struct ReasonFile<'a> {
+    source: &'a Path,
+    reason: QuarantineReason,
+    message: String,
+}
+
+/// Copies a file that could not be imported into `unsorted_dir`, keeping its
+/// path relative to the input directory it was found in, and records why
+/// next to it. A different file already quarantined under that path is kept,
+/// and this one gets a numeric suffix, as in the library. Returns where the
+/// copy went.
+pub fn quarantine(path: &PhotoPath, error: &anyhow::Error, unsorted_dir: &Path) -> Result<PathBuf> {
+    let relative = path
+        .input_path
+        .strip_prefix(&path.input_dir)
+        .ok()
+        .filter(|r| !r.as_os_str().is_empty())
+        .or_else(|| path.input_path.file_name().map(Path::new))
+        .unwrap_or(&path.input_path);
+    let destination = free_destination(&path.input_path, &unsorted_dir.join(relative))?;
+
+    let reason = QuarantineReason::of(error);
+
+    warn!(
+        path = %path.input_path.display(),
+        reason = serde_json::to_string(&reason)?.trim_matches('"'),
+        destination = %destination.display(),
+        "Quarantining"
+    );
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if !destination.exists() {
+        std::fs::copy(&path.input_path, &destination)?;
+    }
+
+    let mut reason_path = destination.clone().into_os_string();
+    reason_path.push(".reason.json");
+    std::fs::write(
+        reason_path,
+        serde_json::to_string_pretty(&ReasonFile {
+            source: &path.input_path,
+            reason,
+            message: format!("{:#}", error),
+        })?,
+    )?;
+
+    Ok(destination)
+}
+
+/// `destination`, or the first of `name_1.ext`, `name_2.ext`, ... next to it
+/// that is free or already holds a copy of `source`.
+fn free_destination(source: &Path, destination: &Path) -> Result<PathBuf> {
+    let checksum = checksum_file(source)?;
+    let stem = destination
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let extension = destination
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let candidates = std::iter::once(destination.to_path_buf())
+        .chain((1..).map(|n| destination.with_file_name(format!("{}_{}{}", stem, n, extension))));
+    for candidate in candidates {
+        if !candidate.exists() || checksum_file(&candidate)? == checksum {
+            return Ok(candidate);
+        }
+    }
+
+    unreachable!("numeric suffixes are unbounded")
+}
@@ -0,0 +1,248 @@
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::checksum::{checksum_file, Checksum};
+use crate::exif::{merge_sidecar, Exif};
+use crate::progress::Progress;
+use crate::{date_photo, destination, find_all_photos, split_extension, PhotoPath, State};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::error;
+
+/// What `rebuild-index` made of one file in the library.
+#[derive(Debug)]
+pub enum IndexOutcome {
+    Indexed,
+    /// Indexed, but not where the current settings would put it.
+    Misplaced {
+        expected: String,
+    },
+    /// Indexed, but where it belongs can't be worked out, e.g. for lack of a
+    /// capture date.
+    Unplaced {
+        reason: String,
+    },
+    /// Left out, as an earlier file has the same contents.
+    Duplicate {
+        of: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct IndexedFile {
+    /// Relative to the library root.
+    pub path: String,
+    pub outcome: IndexOutcome,
+}
+
+#[derive(Debug)]
+pub struct RebuildReport {
+    pub files: Vec<IndexedFile>,
+    /// Catalog entries dropped because no file in the library matched them,
+    /// by path or by contents.
+    pub removed: usize,
+}
+
+/// Hashes and reads back every file in the library, skipping the unsorted
+/// directory, and makes the catalog match what is on disk.
+pub fn rebuild_index(state: &State, catalog: &mut Catalog) -> Result<RebuildReport> {
+    let found = find_all_photos(&state.output_dir, &state.photo_matcher)
+        .into_iter()
+        .filter(|p| !p.input_path.starts_with(&state.unsorted_dir))
+        .map(|p| {
+            let size = std::fs::metadata(&p.input_path).map_or(0, |m| m.len());
+            (p, size)
+        })
+        .collect::<Vec<_>>();
+
+    let progress = Progress::start(
+        "Indexing",
+        found.len(),
+        found.iter().map(|(_, size)| size).sum(),
+        state.progress,
+    );
+
+    let mut indexed = found
+        .into_par_iter()
+        .map(|(p, size)| {
+            let path = relative_path(&p.input_path, &state.output_dir);
+            let result = index_file(&p, &path, state);
+            progress.inc(size);
+            (path, result)
+        })
+        .collect::<Vec<_>>();
+
+    progress.finish();
+
+    // Of several copies of the same file, keep one that is where it belongs.
+    indexed.sort_by_key(|(_, result)| !matches!(result, Ok((_, IndexOutcome::Indexed))));
+
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+    let mut seen: HashMap<Checksum, String> = HashMap::new();
+
+    for (path, result) in indexed {
+        let outcome = match result {
+            Err(e) => {
+                error!(path = %path, "{:#}", e);
+                IndexOutcome::Error {
+                    message: format!("{:#}", e),
+                }
+            }
+            Ok((entry, _)) if seen.contains_key(&entry.source) => IndexOutcome::Duplicate {
+                of: seen[&entry.source].clone(),
+            },
+            Ok((entry, placement)) => {
+                seen.insert(entry.source.clone(), path.clone());
+                entries.push(entry);
+                placement
+            }
+        };
+
+        files.push(IndexedFile { path, outcome });
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let removed = catalog.replace_all(&entries)?;
+
+    Ok(RebuildReport { files, removed })
+}
+
+fn relative_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads back the library file at `relative`, returning its catalog entry and
+/// whether it is where the current settings would put it.
+fn index_file(
+    path: &PhotoPath,
+    relative: &str,
+    state: &State,
+) -> Result<(CatalogEntry, IndexOutcome)> {
+    let checksum = checksum_file(&path.input_path)?;
+
+    let mut exif = state.metadata.read(&path.input_path)?;
+    merge_sidecar(&path.input_path, &mut exif)?;
+
+    // Name-based placeholders and date sources refer to the file as it was
+    // before it was imported.
+    let original = exif
+        .original_filename
+        .as_ref()
+        .map(|name| path.input_path.with_file_name(name))
+        .unwrap_or_else(|| path.input_path.clone());
+
+    let placement = match expected_destinations(&exif, &original, &checksum, state) {
+        Ok(expected) if expected.iter().any(|e| follows_layout(relative, e)) => {
+            IndexOutcome::Indexed
+        }
+        Ok(expected) => IndexOutcome::Misplaced {
+            expected: expected.into_iter().next().unwrap_or_default(),
+        },
+        Err(e) => IndexOutcome::Unplaced {
+            reason: format!("{:#}", e),
+        },
+    };
+
+    date_photo(&mut exif, &original, state);
+
+    // Which source the file was imported from can't be told from the file;
+    // the catalog keeps it for files it already knows.
+    let entry = CatalogEntry {
+        file: Some(checksum.clone()),
+        ..CatalogEntry::new(relative.to_string(), checksum, &exif)
+    };
+
+    Ok((entry, placement))
+}
+
+/// Where a file with metadata `exif` would be imported to now. A file whose
+/// camera has a clock correction may have been named from either the
+/// recorded or the corrected time, depending on whether the corrected date
+/// was written back, so both are accepted.
+fn expected_destinations(
+    exif: &Exif,
+    original: &Path,
+    checksum: &Checksum,
+    state: &State,
+) -> Result<Vec<String>> {
+    let mut corrected = exif.clone();
+    let candidates = if state.clock_corrections.correct(&mut corrected) {
+        vec![exif.clone(), corrected]
+    } else {
+        vec![exif.clone()]
+    };
+
+    candidates
+        .into_iter()
+        .map(|mut exif| {
+            date_photo(&mut exif, original, state);
+            Ok(destination(&exif, original, checksum, 0, state)?.0)
+        })
+        .collect()
+}
+
+/// Whether `actual` is `expected`, allowing for the `-<subsec>` and `_<n>`
+/// suffixes added to resolve name collisions.
+fn follows_layout(actual: &str, expected: &str) -> bool {
+    let (actual_stem, actual_extension) = split_extension(actual);
+    let (stem, extension) = split_extension(expected);
+
+    if actual_extension != extension {
+        return false;
+    }
+
+    match actual_stem.strip_prefix(&stem) {
+        Some("") => true,
+        Some(suffix) => suffix
+            .strip_prefix('_')
+            .or_else(|| suffix.strip_prefix('-'))
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())),
+        None => false,
+    }
+}
+
+pub fn print_rebuild(report: &RebuildReport) {
+    let mut misplaced = 0;
+    let mut unplaced = 0;
+    let mut duplicates = 0;
+    let mut errors = 0;
+
+    for file in &report.files {
+        match &file.outcome {
+            IndexOutcome::Indexed => {}
+            IndexOutcome::Misplaced { expected } => {
+                misplaced += 1;
+                println!("{} (expected at {})", file.path, expected);
+            }
+            IndexOutcome::Unplaced { reason } => {
+                unplaced += 1;
+                println!("{} (can't tell where it belongs: {})", file.path, reason);
+            }
+            IndexOutcome::Duplicate { of } => {
+                duplicates += 1;
+                println!("{} (duplicate of {}, not indexed)", file.path, of);
+            }
+            IndexOutcome::Error { message } => {
+                errors += 1;
+                println!("{} (error: {})", file.path, message);
+            }
+        }
+    }
+
+    println!(
+        "\nIndexed {} files: {} don't follow the current layout, {} can't be placed, {} duplicates, {} errors. Dropped {} catalog entries no file matched.",
+        report.files.len() - duplicates - errors,
+        misplaced,
+        unplaced,
+        duplicates,
+        errors,
+        report.removed
+    );
+}
@@ -0,0 +1,567 @@
+use crate::checksum::Checksum;
+use crate::exif::Exif;
+use crate::timezone::TimeZones;
+use crate::{generate_camera, MissingDateError};
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use std::path::Path;
+
+/// Placeholders accepted in output path templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Date,
+    Time,
+    SubSec,
+    DateTimeOriginal,
+    CreateDate,
+    Album,
+    Camera,
+    Make,
+    Model,
+    GpsLatitude,
+    GpsLongitude,
+    OriginalFilename,
+    OrigName,
+    OrigStem,
+    OrigExt,
+    Checksum,
+    Seq,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("year", Field::Year),
+    ("month", Field::Month),
+    ("day", Field::Day),
+    ("hour", Field::Hour),
+    ("minute", Field::Minute),
+    ("second", Field::Second),
+    ("date", Field::Date),
+    ("time", Field::Time),
+    ("subsec", Field::SubSec),
+    ("date_time_original", Field::DateTimeOriginal),
+    ("create_date", Field::CreateDate),
+    ("album", Field::Album),
+    ("camera", Field::Camera),
+    ("make", Field::Make),
+    ("model", Field::Model),
+    ("gps_latitude", Field::GpsLatitude),
+    ("gps_longitude", Field::GpsLongitude),
+    ("original_filename", Field::OriginalFilename),
+    ("orig_name", Field::OrigName),
+    ("orig_stem", Field::OrigStem),
+    ("orig_ext", Field::OrigExt),
+    ("checksum", Field::Checksum),
+    ("seq", Field::Seq),
+];
+
+/// Widest `{checksum}` prefix: the whole BLAKE3 digest in hex.
+const MAX_CHECKSUM_WIDTH: usize = 64;
+
+/// Widest zero padding for numbers, enough for any `u64`.
+const MAX_NUMBER_WIDTH: usize = 20;
+
+#[derive(Debug, Clone)]
+enum Format {
+    None,
+    /// Zero-padded minimum width for numbers, prefix length for the checksum.
+    Width(usize),
+    Strftime(String),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Placeholder { field: Field, format: Format },
+}
+
+/// Everything a template can draw on when naming a single photo.
+pub struct TemplateContext<'a> {
+    pub exif: &'a Exif,
+    pub input_path: &'a Path,
+    pub checksum: &'a Checksum,
+    pub seq: usize,
+    /// Puts `{date_time_original}` and `{create_date}` in the display zone.
+    pub time_zones: &'a TimeZones,
+}
+
+/// A parsed output path template such as
+/// `{year}/{month:02}/{camera}/{date:%Y%m%d}_{time}_{orig_stem}`.
+///
+/// The rendered path excludes the file extension, which is always taken from
+/// the original file.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => {
+                                return Err(anyhow!(
+                                    "Unterminated placeholder '{{{}' in template '{}'",
+                                    placeholder,
+                                    template
+                                ))
+                            }
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(&placeholder)?);
+                }
+                '}' => {
+                    return Err(anyhow!(
+                        "Unmatched '}}' in template '{}' (use '}}}}' for a literal brace)",
+                        template
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err(anyhow!("Template must not be empty"));
+        }
+
+        if let Some(Segment::Literal(l)) = segments.first() {
+            if l.starts_with(['/', '\\']) {
+                return Err(anyhow!(
+                    "Template '{}' must be relative to the output directory",
+                    template
+                ));
+            }
+        }
+
+        // Placeholders stand in as `{}`, so only a whole `..` component is
+        // caught here; what they render to is checked in `render`.
+        let literals = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(l) => l.as_str(),
+                Segment::Placeholder { .. } => "{}",
+            })
+            .collect::<String>();
+        if literals.split(['/', '\\']).any(|part| part == "..") {
+            return Err(anyhow!(
+                "Template '{}' must not refer to a parent directory with '..'",
+                template
+            ));
+        }
+
+        Ok(Template { segments })
+    }
+
+    /// Renders the path for one photo. Fails if a value substituted into it
+    /// would place the file outside the output directory.
+    pub fn render(&self, ctx: &TemplateContext) -> Result<String> {
+        let mut s = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(l) => s.push_str(l),
+                Segment::Placeholder { field, format } => {
+                    s.push_str(&render_field(*field, format, ctx)?)
+                }
+            }
+        }
+
+        if s.starts_with('/') || s.split('/').any(|part| part == "..") {
+            return Err(anyhow!(
+                "Output path '{}' for {} would be outside the output directory",
+                s,
+                ctx.input_path.display()
+            ));
+        }
+
+        Ok(s)
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Segment> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec)),
+        None => (placeholder.trim(), None),
+    };
+
+    let field = FIELDS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, f)| *f)
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown placeholder '{{{}}}' in template; expected one of: {}",
+                name,
+                FIELDS
+                    .iter()
+                    .map(|(n, _)| *n)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+
+    let format = match (field, spec) {
+        (_, None) => Format::None,
+        (
+            Field::Year
+            | Field::Month
+            | Field::Day
+            | Field::Hour
+            | Field::Minute
+            | Field::Second
+            | Field::Seq
+            | Field::Checksum,
+            Some(spec),
+        ) => {
+            let max = if field == Field::Checksum {
+                MAX_CHECKSUM_WIDTH
+            } else {
+                MAX_NUMBER_WIDTH
+            };
+            match spec.parse::<usize>() {
+                Ok(width) if (1..=max).contains(&width) => Format::Width(width),
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid format '{}' for placeholder '{{{}}}': expected a width from 1 to {}, such as 02",
+                        spec,
+                        name,
+                        max
+                    ))
+                }
+            }
+        }
+        (Field::Date | Field::Time | Field::DateTimeOriginal | Field::CreateDate, Some(spec)) => {
+            if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+                return Err(anyhow!(
+                    "Invalid date format '{}' for placeholder '{{{}}}'",
+                    spec,
+                    name
+                ));
+            }
+            Format::Strftime(spec.to_string())
+        }
+        (_, Some(spec)) => {
+            return Err(anyhow!(
+                "Placeholder '{{{}}}' does not accept a format (got '{}')",
+                name,
+                spec
+            ))
+        }
+    };
+
+    Ok(Segment::Placeholder { field, format })
+}
+
+fn capture_date(exif: &Exif) -> Result<NaiveDateTime> {
+    Ok(exif.capture_date().ok_or(MissingDateError)?)
+}
+
+/// `date` in the display zone. A time that doesn't exist there, in a DST
+/// gap, is left as it is.
+fn normalize(date: NaiveDateTime, ctx: &TemplateContext) -> NaiveDateTime {
+    ctx.time_zones.normalize(ctx.exif, date).unwrap_or(date)
+}
+
+fn render_number(n: u64, format: &Format) -> String {
+    match format {
+        Format::Width(width) => format!("{:0width$}", n, width = *width),
+        _ => n.to_string(),
+    }
+}
+
+fn render_date(date: NaiveDateTime, format: &Format, default: &str) -> String {
+    match format {
+        Format::Strftime(f) => date.format(f).to_string(),
+        _ => date.format(default).to_string(),
+    }
+}
+
+/// Values taken from the photo must not introduce extra directory levels.
+fn sanitize(value: &str) -> String {
+    value.replace(['/', '\\'], "_")
+}
+
+fn render_text(value: Option<&str>, field: Field) -> String {
+    match value {
+        Some(v) => sanitize(v),
+        None => {
+            let name = FIELDS
+                .iter()
+                .find(|(_, f)| *f == field)
+                .map(|(n, _)| *n)
+                .unwrap_or_default();
+            format!("unknown {}", name.replace('_', " "))
+        }
+    }
+}
+
+fn render_field(field: Field, format: &Format, ctx: &TemplateContext) -> Result<String> {
+    let exif = ctx.exif;
+
+    Ok(match field {
+        Field::Year => render_number(capture_date(exif)?.year() as u64, format),
+        Field::Month => render_number(capture_date(exif)?.month() as u64, format),
+        Field::Day => render_number(capture_date(exif)?.day() as u64, format),
+        Field::Hour => render_number(capture_date(exif)?.hour() as u64, format),
+        Field::Minute => render_number(capture_date(exif)?.minute() as u64, format),
+        Field::Second => render_number(capture_date(exif)?.second() as u64, format),
+        Field::Date => render_date(capture_date(exif)?, format, "%Y-%m-%d"),
+        Field::Time => render_date(capture_date(exif)?, format, "%H-%M-%S"),
+        Field::SubSec => render_text(exif.sub_sec_time_original.as_deref(), field),
+        Field::DateTimeOriginal => match exif.date_time_original {
+            Some(date) => render_date(normalize(date, ctx), format, "%Y-%m-%d_%H-%M-%S"),
+            None => render_text(None, field),
+        },
+        Field::CreateDate => match exif.create_date {
+            Some(date) => render_date(normalize(date, ctx), format, "%Y-%m-%d_%H-%M-%S"),
+            None => render_text(None, field),
+        },
+        Field::Album => render_text(exif.album.as_deref(), field),
+        Field::Camera => render_text(generate_camera(exif).as_deref(), field),
+        Field::Make => render_text(exif.camera_make().map(|s| s.as_str()), field),
+        Field::Model => render_text(exif.camera_model().map(|s| s.as_str()), field),
+        Field::GpsLatitude => render_text(exif.gps_latitude.as_deref(), field),
+        Field::GpsLongitude => render_text(exif.gps_longitude.as_deref(), field),
+        Field::OriginalFilename => render_text(exif.original_filename.as_deref(), field),
+        Field::OrigName => render_text(
+            ctx.input_path
+                .file_name()
+                .map(|s| s.to_string_lossy())
+                .as_deref(),
+            field,
+        ),
+        Field::OrigStem => render_text(
+            ctx.input_path
+                .file_stem()
+                .map(|s| s.to_string_lossy())
+                .as_deref(),
+            field,
+        ),
+        Field::OrigExt => render_text(
+            ctx.input_path
+                .extension()
+                .map(|s| s.to_string_lossy())
+                .as_deref(),
+            field,
+        ),
+        Field::Checksum => {
+            let len = match format {
+                Format::Width(len) => *len,
+                _ => 8,
+            };
+            ctx.checksum.blake3.chars().take(len).collect()
+        }
+        Field::Seq => render_number(ctx.seq as u64, format),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timezone::DisplayZone;
+    use chrono::{FixedOffset, NaiveDate};
+
+    fn exif() -> Exif {
+        let mut exif = Exif {
+            date_time_original: NaiveDate::from_ymd_opt(2023, 1, 4)
+                .and_then(|d| d.and_hms_opt(10, 15, 0)),
+            create_date: NaiveDate::from_ymd_opt(2023, 1, 4)
+                .and_then(|d| d.and_hms_opt(10, 14, 59)),
+            offset_time_original: Some("+01:00".to_string()),
+            make: Some("Test".to_string()),
+            model: Some("Camera".to_string()),
+            ..Exif::default()
+        };
+        exif.capture_time = TimeZones::default().resolve(&exif);
+        exif
+    }
+
+    fn checksum() -> Checksum {
+        Checksum {
+            blake3: "0123456789abcdef".repeat(4),
+            size: 4,
+        }
+    }
+
+    fn render(template: &str, exif: &Exif, time_zones: &TimeZones) -> Result<String> {
+        Template::parse(template)?.render(&TemplateContext {
+            exif,
+            input_path: Path::new("/photos/IMG_0001.JPG"),
+            checksum: &checksum(),
+            seq: 7,
+            time_zones,
+        })
+    }
+
+    fn parse_error(template: &str) -> String {
+        Template::parse(template).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn renders_placeholders_and_literals() {
+        let rendered = render(
+            "{year}/{month:02}/{camera}/{date:%Y%m%d}_{time}_{orig_stem}.{orig_ext}-{seq:3}",
+            &exif(),
+            &TimeZones::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "2023/01/Test Camera/20230104_10-15-00_IMG_0001.JPG-007"
+        );
+    }
+
+    #[test]
+    fn escapes_braces() {
+        let rendered = render("{{{year}}}", &exif(), &TimeZones::default()).unwrap();
+        assert_eq!(rendered, "{2023}");
+    }
+
+    #[test]
+    fn takes_a_checksum_prefix() {
+        let time_zones = TimeZones::default();
+        assert_eq!(
+            render("{checksum}", &exif(), &time_zones).unwrap(),
+            "01234567"
+        );
+        assert_eq!(
+            render("{checksum:4}", &exif(), &time_zones).unwrap(),
+            "0123"
+        );
+        assert_eq!(
+            render("{checksum:64}", &exif(), &time_zones).unwrap(),
+            checksum().blake3
+        );
+    }
+
+    #[test]
+    fn rejects_widths_out_of_range() {
+        for template in [
+            "{checksum:0}",
+            "{checksum:65}",
+            "{month:0}",
+            "{seq:21}",
+            "{day:x}",
+        ] {
+            assert!(
+                parse_error(template).contains("expected a width from 1 to"),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(parse_error("").contains("must not be empty"));
+        assert!(parse_error("{year").contains("Unterminated placeholder"));
+        assert!(parse_error("year}").contains("Unmatched '}'"));
+        assert!(parse_error("{lens}").contains("Unknown placeholder '{lens}'"));
+        assert!(parse_error("{camera:02}").contains("does not accept a format"));
+        assert!(parse_error("{date:%Q}").contains("Invalid date format"));
+    }
+
+    #[test]
+    fn rejects_templates_leaving_the_output_directory() {
+        assert!(parse_error("/{year}").contains("must be relative"));
+        for template in ["../{year}", "{year}/../{month}", "{year}/.."] {
+            assert!(parse_error(template).contains("'..'"), "{}", template);
+        }
+        assert!(Template::parse("{year}..{month}").is_ok());
+    }
+
+    #[test]
+    fn keeps_values_within_one_directory_level() {
+        let time_zones = TimeZones::default();
+        let with_album = |album: &str| Exif {
+            album: Some(album.to_string()),
+            ..exif()
+        };
+
+        assert_eq!(
+            render("{album}/{date}", &with_album("a/b\\c"), &time_zones).unwrap(),
+            "a_b_c/2023-01-04"
+        );
+        assert_eq!(
+            render("{album}/{date}", &exif(), &time_zones).unwrap(),
+            "unknown album/2023-01-04"
+        );
+
+        for album in ["..", ""] {
+            let error = render("{album}/{date}", &with_album(album), &time_zones).unwrap_err();
+            assert!(
+                error.to_string().contains("outside the output directory"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn renders_embedded_dates_in_the_display_zone() {
+        let template = "{date_time_original}+{create_date:%H%M%S}";
+        assert_eq!(
+            render(template, &exif(), &TimeZones::default()).unwrap(),
+            "2023-01-04_10-15-00+101459"
+        );
+
+        // Taken at +01:00 according to OffsetTimeOriginal.
+        let utc = TimeZones {
+            display: Some(DisplayZone::Fixed(FixedOffset::east_opt(0).unwrap())),
+            ..TimeZones::default()
+        };
+        assert_eq!(
+            render(template, &exif(), &utc).unwrap(),
+            "2023-01-04_09-15-00+091459"
+        );
+    }
+
+    #[test]
+    fn fails_without_a_capture_date() {
+        let exif = Exif {
+            date_time_original: None,
+            create_date: None,
+            capture_time: None,
+            ..exif()
+        };
+        let error = render("{year}", &exif, &TimeZones::default()).unwrap_err();
+        assert!(error.is::<MissingDateError>());
+        assert_eq!(
+            render("{date_time_original}", &exif, &TimeZones::default()).unwrap(),
+            "unknown date time original"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::exif::Exif;
+use crate::generate_camera;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, TimeZone};
+use std::collections::HashMap;
+
+/// Parses a UTC offset such as `+02:00`, `-0530`, `+09` or `UTC`.
+pub fn parse_offset(s: &str) -> Result<FixedOffset> {
+    let s = s.trim();
+    let invalid = || anyhow!("Invalid UTC offset '{}': expected e.g. +02:00 or -0530", s);
+
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return FixedOffset::east_opt(0).ok_or_else(invalid);
+    }
+
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+
+    let digits = s[1..].replace(':', "");
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let (hours, minutes) = match digits.len() {
+        2 => (digits[..2].parse::<i32>()?, 0),
+        4 => (digits[..2].parse::<i32>()?, digits[2..].parse::<i32>()?),
+        _ => return Err(invalid()),
+    };
+
+    if hours > 14 || minutes >= 60 {
+        return Err(invalid());
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+/// Zone that capture times are expressed in when naming files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayZone {
+    /// The time zone of the machine running the import.
+    Local,
+    Fixed(FixedOffset),
+}
+
+/// Parses `local` or a UTC offset accepted by [`parse_offset`].
+pub fn parse_display_zone(s: &str) -> Result<DisplayZone> {
+    if s.trim().eq_ignore_ascii_case("local") {
+        Ok(DisplayZone::Local)
+    } else {
+        parse_offset(s).map(DisplayZone::Fixed)
+    }
+}
+
+/// Decides which UTC offset each capture was taken at, and which zone its time
+/// is shown in.
+#[derive(Debug, Clone, Default)]
+pub struct TimeZones {
+    /// Offset every camera clock in this import was set to, from `--camera-tz`.
+    pub camera_tz: Option<FixedOffset>,
+    /// Offsets individual camera clocks were set to, keyed by make and model
+    /// as rendered by `{camera}`.
+    pub camera_offsets: HashMap<String, FixedOffset>,
+    /// `None` keeps each capture in the zone it was taken in.
+    pub display: Option<DisplayZone>,
+}
+
+impl TimeZones {
+    /// Works out the capture time of `exif` as an instant, then expresses it in
+    /// the display zone.
+    ///
+    /// EXIF dates are wall-clock times. Their offset comes from, in order: the
+    /// `--camera-tz` override, the per-camera table, `OffsetTimeOriginal`, the
+    /// difference to the GPS timestamp, and finally the local time zone.
+    /// QuickTime dates are already UTC. Fallback dates, such as those taken
+    /// from file names, are in the local time zone.
+    pub fn resolve(&self, exif: &Exif) -> Option<DateTime<FixedOffset>> {
+        if let Some(local) = exif.fallback_date {
+            let captured = local_offset(&local).from_local_datetime(&local).single()?;
+            return Some(self.display(captured));
+        }
+
+        let captured = match exif.date_time_original.or(exif.create_date) {
+            Some(local) => self
+                .camera_offset(exif, &local)
+                .from_local_datetime(&local)
+                .single()?,
+            None => {
+                let utc = exif
+                    .quicktime_create_date
+                    .or(exif.quicktime_media_create_date)?;
+                self.configured_offset(exif)
+                    .unwrap_or_else(|| Local.offset_from_utc_datetime(&utc).fix())
+                    .from_utc_datetime(&utc)
+            }
+        };
+
+        Some(self.display(captured))
+    }
+
+    /// Expresses `local`, a wall-clock time from the camera that took `exif`
+    /// such as its `CreateDate`, in the display zone, the same way
+    /// [`TimeZones::resolve`] does for the capture time.
+    pub fn normalize(&self, exif: &Exif, local: NaiveDateTime) -> Option<NaiveDateTime> {
+        let captured = self
+            .camera_offset(exif, &local)
+            .from_local_datetime(&local)
+            .single()?;
+        Some(self.display(captured).naive_local())
+    }
+
+    /// The offset configured for the camera that took `exif`, if any.
+    fn configured_offset(&self, exif: &Exif) -> Option<FixedOffset> {
+        self.camera_tz.or_else(|| {
+            generate_camera(exif).and_then(|camera| self.camera_offsets.get(&camera).copied())
+        })
+    }
+
+    /// The offset the camera's clock was set to when it read `local`.
+    fn camera_offset(&self, exif: &Exif, local: &NaiveDateTime) -> FixedOffset {
+        self.configured_offset(exif)
+            .or_else(|| exif.offset_time())
+            .or_else(|| exif.gps_offset())
+            .unwrap_or_else(|| local_offset(local))
+    }
+
+    fn display(&self, captured: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.display {
+            None => captured,
+            Some(DisplayZone::Local) => {
+                let local = captured.with_timezone(&Local);
+                local.with_timezone(&local.offset().fix())
+            }
+            Some(DisplayZone::Fixed(offset)) => captured.with_timezone(&offset),
+        }
+    }
+}
+
+/// Offset of the local time zone at the wall-clock time `local`.
+fn local_offset(local: &NaiveDateTime) -> FixedOffset {
+    Local
+        .offset_from_local_datetime(local)
+        .earliest()
+        .unwrap_or_else(|| Local.offset_from_utc_datetime(local))
+        .fix()
+}
@@ -0,0 +1,156 @@
+use crate::checksum::{checksum_file, Checksum};
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::Path;
+use tracing::debug;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Hard link, sharing the source's inode
+    Hard,
+    /// Copy-on-write clone, e.g. on btrfs, XFS or APFS
+    Reflink,
+}
+
+/// How a photo gets from its source into the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    Copy,
+    /// Removes the source once the library copy has been verified.
+    Move,
+    /// Links to the source where the filesystem allows it, copying otherwise.
+    Link(LinkMode),
+}
+
+impl TransferMode {
+    /// Whether the library file may share its data with the source. Metadata
+    /// must then go to a sidecar, as writing it in place could change the
+    /// source.
+    pub fn is_linked(self) -> bool {
+        matches!(self, TransferMode::Link(_))
+    }
+}
+
+/// Places `source` at `destination`, which must not exist yet. `expected` is
+/// the checksum the source had when it was scanned. Returns the number of
+/// bytes copied, which is zero when the file was renamed or linked.
+pub fn transfer(
+    source: &Path,
+    destination: &Path,
+    expected: &Checksum,
+    mode: TransferMode,
+) -> Result<u64> {
+    match mode {
+        TransferMode::Copy => Ok(fs::copy(source, destination)?),
+        TransferMode::Move => move_file(source, destination, expected),
+        TransferMode::Link(link) => link_file(source, destination, link),
+    }
+}
+
+fn link_file(source: &Path, destination: &Path, link: LinkMode) -> Result<u64> {
+    let linked = match link {
+        LinkMode::Hard => fs::hard_link(source, destination),
+        LinkMode::Reflink => reflink_copy::reflink(source, destination),
+    };
+
+    match linked {
+        Ok(()) => Ok(0),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e.into()),
+        Err(e) => {
+            debug!(path = %source.display(), "Unable to link ({}), copying instead", e);
+            // A failed clone can leave an empty file behind.
+            let _ = fs::remove_file(destination);
+            Ok(fs::copy(source, destination)?)
+        }
+    }
+}
+
+/// Renames the source into place when it lives on the same filesystem, which
+/// never exposes a partial file, once it has been re-hashed to make sure it
+/// is still what was scanned. Otherwise copies it, forces the copy to disk,
+/// re-hashes it and only removes the source once the hashes match. Either way
+/// an existing destination is never replaced, and on any failure the source
+/// is left untouched.
+fn move_file(source: &Path, destination: &Path, expected: &Checksum) -> Result<u64> {
+    // `rename` silently replaces its target on unix.
+    if destination.exists() {
+        return Err(anyhow!(
+            "Unable to move {} to {}: the destination already exists",
+            source.display(),
+            destination.display()
+        ));
+    }
+
+    let actual = checksum_file(source)?;
+    if &actual != expected {
+        return Err(anyhow!(
+            "{} has changed since it was scanned (checksum {}, expected {}), leaving it in place",
+            source.display(),
+            actual,
+            expected
+        ));
+    }
+
+    match fs::rename(source, destination) {
+        Ok(()) => return Ok(0),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+        Err(e) => {
+            return Err(anyhow!(
+                "Unable to move {} to {}: {}",
+                source.display(),
+                destination.display(),
+                e
+            ))
+        }
+    }
+
+    let copied = match copy_verified(source, destination, expected) {
+        Ok(copied) => copied,
+        Err(e) => {
+            let _ = fs::remove_file(destination);
+            return Err(e.context(format!(
+                "Verification of {} failed, leaving the source in place",
+                source.display()
+            )));
+        }
+    };
+
+    fs::remove_file(source)
+        .with_context(|| format!("Copied but unable to remove {}", source.display()))?;
+
+    Ok(copied)
+}
+
+fn copy_verified(source: &Path, destination: &Path, expected: &Checksum) -> Result<u64> {
+    let copied = fs::copy(source, destination)?;
+    File::open(destination)?.sync_all()?;
+
+    if let Some(parent) = destination.parent() {
+        sync_dir(parent)?;
+    }
+
+    let actual = checksum_file(destination)?;
+
+    if &actual != expected {
+        return Err(anyhow!(
+            "{} has checksum {}, expected {}",
+            destination.display(),
+            actual,
+            expected
+        ));
+    }
+
+    Ok(copied)
+}
+
+/// Makes the new directory entry durable, not just the file contents.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    Ok(File::open(dir)?.sync_all()?)
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
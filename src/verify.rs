@@ -0,0 +1,200 @@
+use crate::catalog::{Catalog, DueFile};
+use crate::checksum::{checksum_file, Checksum};
+use crate::progress::Progress;
+use crate::{find_all_photos, State};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use rayon::prelude::*;
+use std::io::ErrorKind;
+use tracing::error;
+
+/// What `verify` found for one file.
+#[derive(Debug)]
+pub enum VerifyOutcome {
+    Intact,
+    /// The file's contents no longer match the checksum it was imported with.
+    Corrupt {
+        actual: Checksum,
+    },
+    /// The catalog had no checksum for the file, so the one found now is
+    /// recorded for next time.
+    Fingerprinted {
+        actual: Checksum,
+    },
+    Missing,
+    /// On disk, but not in the catalog.
+    Unindexed,
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct VerifiedFile {
+    /// Relative to the library root.
+    pub path: String,
+    pub expected: Option<Checksum>,
+    pub outcome: VerifyOutcome,
+}
+
+/// Which catalog entries to re-hash.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selection {
+    /// Only files not verified within this many days.
+    pub not_verified_for: Option<u32>,
+    /// At most this many files, picked at random.
+    pub sample: Option<usize>,
+}
+
+/// Re-hashes library files recorded in the catalog, and looks for library
+/// files the catalog doesn't know. Intact files get their verification time
+/// updated, as do files the catalog had no checksum for, which get the one
+/// found now.
+pub fn verify_library(
+    state: &State,
+    catalog: &mut Catalog,
+    selection: Selection,
+) -> Result<Vec<VerifiedFile>> {
+    let started = Utc::now();
+    let due = catalog.due_for_verification(
+        selection
+            .not_verified_for
+            .map(|days| started - Duration::days(days.into())),
+        selection.sample,
+    )?;
+
+    let progress = Progress::start(
+        "Verifying",
+        due.len(),
+        due.iter().map(|file| file.size).sum(),
+        state.progress,
+    );
+
+    let mut files = due
+        .into_par_iter()
+        .map(|DueFile { path, file, size }| {
+            let outcome = match checksum_file(state.output_dir.join(&path)) {
+                Ok(actual) if file.is_none() => VerifyOutcome::Fingerprinted { actual },
+                Ok(actual) if Some(&actual) == file.as_ref() => VerifyOutcome::Intact,
+                Ok(actual) => VerifyOutcome::Corrupt { actual },
+                Err(e)
+                    if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                        == Some(ErrorKind::NotFound) =>
+                {
+                    VerifyOutcome::Missing
+                }
+                Err(e) => {
+                    error!(path = %path, "{:#}", e);
+                    VerifyOutcome::Error {
+                        message: format!("{:#}", e),
+                    }
+                }
+            };
+            progress.inc(size);
+
+            VerifiedFile {
+                path,
+                expected: file,
+                outcome,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    progress.finish();
+
+    let indexed = catalog.paths()?;
+    files.extend(
+        find_all_photos(&state.output_dir, &state.photo_matcher)
+            .into_iter()
+            .filter(|p| !p.input_path.starts_with(&state.unsorted_dir))
+            .filter_map(|p| {
+                let path = p
+                    .input_path
+                    .strip_prefix(&state.output_dir)
+                    .ok()?
+                    .to_string_lossy()
+                    .into_owned();
+                (!indexed.contains(&path)).then_some(VerifiedFile {
+                    path,
+                    expected: None,
+                    outcome: VerifyOutcome::Unindexed,
+                })
+            }),
+    );
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    catalog.mark_verified(
+        &files
+            .iter()
+            .filter_map(|f| match &f.outcome {
+                VerifyOutcome::Intact => Some((f.path.as_str(), f.expected.as_ref()?)),
+                VerifyOutcome::Fingerprinted { actual } => Some((f.path.as_str(), actual)),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        started,
+    )?;
+
+    Ok(files)
+}
+
+/// Prints every problem found and a summary. Returns the number of problems.
+pub fn print_verification(files: &[VerifiedFile]) -> usize {
+    let mut verified = 0;
+    let mut fingerprinted = 0;
+    let mut corrupt = 0;
+    let mut missing = 0;
+    let mut unindexed = 0;
+    let mut errors = 0;
+
+    for file in files {
+        match &file.outcome {
+            VerifyOutcome::Intact => verified += 1,
+            VerifyOutcome::Fingerprinted { .. } => {
+                verified += 1;
+                fingerprinted += 1;
+            }
+            VerifyOutcome::Corrupt { actual } => {
+                verified += 1;
+                corrupt += 1;
+                println!(
+                    "{} (corrupt: checksum {}, expected {})",
+                    file.path,
+                    actual,
+                    file.expected
+                        .as_ref()
+                        .map(|c| c.to_string())
+                        .unwrap_or_default()
+                );
+            }
+            VerifyOutcome::Missing => {
+                verified += 1;
+                missing += 1;
+                println!("{} (missing)", file.path);
+            }
+            VerifyOutcome::Unindexed => {
+                unindexed += 1;
+                println!("{} (not in the catalog)", file.path);
+            }
+            VerifyOutcome::Error { message } => {
+                verified += 1;
+                errors += 1;
+                println!("{} (error: {})", file.path, message);
+            }
+        }
+    }
+
+    println!(
+        "\nVerified {} files: {} corrupt, {} missing, {} errors. {} files are not in the catalog.",
+        verified, corrupt, missing, errors, unindexed
+    );
+    if fingerprinted > 0 {
+        println!(
+            "{} files had no checksum in the catalog; theirs are recorded now.",
+            fingerprinted
+        );
+    }
+
+    corrupt + missing + unindexed + errors
+}
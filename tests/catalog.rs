@@ -0,0 +1,281 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use rusqlite::Connection;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn migrates_photohash_db_and_records_imports() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let report = sandbox.path().join("report.json");
+
+    fs::create_dir_all(output.join("old")).unwrap();
+
+    fs::write(output.join("old/kept.jpg"), "kept").unwrap();
+    let mut legacy = PickleDb::new(
+        output.join("photohash.db"),
+        PickleDbDumpPolicy::AutoDump,
+        SerializationMethod::Json,
+    );
+    legacy
+        .set(
+            &format!("blake3:{}:4", blake3::hash(b"kept").to_hex()),
+            &"old/kept.jpg".to_string(),
+        )
+        .unwrap();
+    // An adler32 key from before BLAKE3, for a file that is gone.
+    legacy.set("123456", &"old/gone.jpg".to_string()).unwrap();
+
+    sandbox.write("again.jpg", "kept");
+    sandbox.write("new.jpg", "new");
+
+    sandbox.import(&["--report", report.to_str().unwrap()]);
+
+    assert!(!output.join("photohash.db").exists());
+    assert!(output.join("photohash.db.migrated").exists());
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report[0]["status"], "duplicate");
+    assert_eq!(report[0]["of"], "old/kept.jpg");
+    assert_eq!(report[1]["status"], "import");
+
+    let catalog = Connection::open(output.join("catalog.db")).unwrap();
+    let paths = catalog
+        .prepare("SELECT path FROM photos ORDER BY path")
+        .unwrap()
+        .query_map([], |row| row.get::<_, String>(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(paths.len(), 2);
+    assert_eq!(paths[0], "old/kept.jpg");
+
+    let (camera, capture_time, batch): (String, String, i64) = catalog
+        .query_row(
+            "SELECT camera, capture_time, batch FROM photos WHERE path = ?1",
+            [&paths[1]],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(camera, "Test Camera");
+    assert!(capture_time.starts_with("2023-01-04T10:15:00"));
+    assert_eq!(batch, 1);
+}
+
+#[test]
+fn refuses_an_unreadable_catalog_unless_rebuilding() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+
+    fs::create_dir_all(output).unwrap();
+    sandbox.write("a.jpg", "a");
+
+    let import = |extra: &[&str]| {
+        sandbox.run(
+            &[
+                &["import", "--metadata-backend", "exiftool"],
+                extra,
+                &["--output", output.to_str().unwrap()],
+                &[sandbox.input.to_str().unwrap()],
+            ]
+            .concat(),
+        )
+    };
+
+    // A truncated catalog is an empty file, which SQLite would happily reuse.
+    fs::write(output.join("catalog.db"), "").unwrap();
+
+    let result = import(&[]);
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("--rebuild-db"));
+    assert_eq!(fs::read(output.join("catalog.db")).unwrap(), b"");
+
+    assert!(import(&["--rebuild-db"]).status.success());
+    let set_aside = fs::read_dir(output)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .starts_with("catalog.db.corrupt-")
+        })
+        .count();
+    assert_eq!(set_aside, 1);
+
+    // Only imports back the catalog up, not commands that only read or
+    // replace it.
+    let output_arg = output.to_str().unwrap();
+    for command in ["verify", "rebuild-index"] {
+        sandbox.run(&[command, output_arg, "--metadata-backend", "exiftool"]);
+    }
+    assert!(!output.join("catalog.db.1").exists());
+
+    // Each later import backs the catalog up first.
+    assert!(import(&[]).status.success());
+    let backup = Connection::open(output.join("catalog.db.1")).unwrap();
+    let count: i64 = backup
+        .query_row("SELECT count(*) FROM photos", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn records_the_source_and_the_library_file_apart() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let source = sandbox.add_fixture("photo.jpg", "photo.jpg");
+
+    // No sidecar: the native backend embeds XMP into the copy.
+    let result = sandbox.run(&[
+        "import",
+        "--metadata-backend",
+        "native",
+        "--output",
+        output.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+
+    let catalog = Connection::open(output.join("catalog.db")).unwrap();
+    let (path, source_hash, file_hash): (String, String, String) = catalog
+        .query_row(
+            "SELECT path, source_hash, file_hash FROM photos",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    let copy = fs::read(output.join(path)).unwrap();
+
+    assert_eq!(
+        source_hash,
+        blake3::hash(&fs::read(source).unwrap()).to_hex().as_str()
+    );
+    assert_eq!(file_hash, blake3::hash(&copy).to_hex().as_str());
+    assert_ne!(source_hash, file_hash);
+}
+
+#[test]
+fn leaves_no_write_ahead_log_behind() {
+    let sandbox = Sandbox::new();
+    sandbox.write("a.jpg", "a");
+
+    sandbox.import(&[]);
+
+    assert!(sandbox.output.join("catalog.db").exists());
+    assert!(!sandbox.output.join("catalog.db-wal").exists());
+    assert!(!sandbox.output.join("catalog.db-shm").exists());
+}
+
+#[test]
+fn dry_runs_read_a_write_ahead_log_left_behind_without_adding_files() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let plan = sandbox.path().join("plan.json");
+    sandbox.write("a.jpg", "a");
+    sandbox.import(&[]);
+
+    let files = || {
+        let mut names = fs::read_dir(output)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    let clean = files();
+
+    // A dry run of a cleanly closed catalog adds no journal files.
+    let dry_run = || {
+        let result = sandbox.run(&[
+            "test",
+            "--output",
+            output.to_str().unwrap(),
+            "--json",
+            plan.to_str().unwrap(),
+            sandbox.input.to_str().unwrap(),
+        ]);
+        assert!(result.status.success(), "{:?}", result);
+        let plan: Vec<Value> = serde_json::from_str(&fs::read_to_string(&plan).unwrap()).unwrap();
+        plan[0].clone()
+    };
+    assert_eq!(dry_run()["status"], "duplicate");
+    assert_eq!(files(), clean);
+
+    // A writer that is still open keeps its change in the log.
+    let writer = Connection::open(output.join("catalog.db")).unwrap();
+    writer
+        .execute("UPDATE photos SET path = 'moved.jpg'", [])
+        .unwrap();
+    let with_log = files();
+    assert!(with_log.contains(&"catalog.db-wal".to_string()));
+
+    assert_eq!(dry_run()["of"], "moved.jpg");
+    assert_eq!(files(), with_log);
+    drop(writer);
+}
+
+#[test]
+fn finds_sources_by_their_adler32_key_after_migration() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let report = sandbox.path().join("report.json");
+
+    // Importing wrote tags into the library file, so it no longer hashes
+    // like the source it was keyed by.
+    fs::create_dir_all(output.join("old")).unwrap();
+    fs::write(output.join("old/tagged.jpg"), "source plus tags").unwrap();
+    let mut legacy = PickleDb::new(
+        output.join("photohash.db"),
+        PickleDbDumpPolicy::AutoDump,
+        SerializationMethod::Json,
+    );
+    legacy
+        .set(
+            &adler32::adler32(&b"source"[..]).unwrap().to_string(),
+            &"old/tagged.jpg".to_string(),
+        )
+        .unwrap();
+
+    // "aca" and "bab" share an adler32 checksum, but the library file
+    // records that it came from another file.
+    fs::write(output.join("old/aca.jpg"), "aca").unwrap();
+    fs::write(
+        output.join("old/aca.jpg.xmp"),
+        r#"<x:xmpmeta><rdf:Description photobot:OriginalFileName="aca.jpg"/></x:xmpmeta>"#,
+    )
+    .unwrap();
+    legacy
+        .set(
+            &adler32::adler32(&b"aca"[..]).unwrap().to_string(),
+            &"old/aca.jpg".to_string(),
+        )
+        .unwrap();
+    assert_eq!(
+        adler32::adler32(&b"aca"[..]).unwrap(),
+        adler32::adler32(&b"bab"[..]).unwrap()
+    );
+
+    sandbox.write("again.jpg", "source");
+    sandbox.write("bab.jpg", "bab");
+    sandbox.import(&["--report", report.to_str().unwrap()]);
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let entry = |name: &str| {
+        report
+            .iter()
+            .find(|e| e["source"].as_str().unwrap().ends_with(name))
+            .unwrap_or_else(|| panic!("{} missing from report", name))
+    };
+    assert_eq!(entry("again.jpg")["status"], "duplicate");
+    assert_eq!(entry("again.jpg")["of"], "old/tagged.jpg");
+    assert_ne!(entry("bab.jpg")["status"], "duplicate");
+    assert!(sandbox
+        .library(&["jpg"])
+        .values()
+        .any(|contents| contents == b"bab"));
+}
@@ -0,0 +1,127 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+/// Imports one photo taken by the fake exiftool's "Test Camera" at
+/// 2023-01-04 10:15:00 with the given clock corrections.
+fn import_with_corrections(corrections: &str) -> Sandbox {
+    let sandbox = Sandbox::new();
+    let config = sandbox.path().join("config.json");
+
+    sandbox.write("IMG_0001.jpg", "photo");
+    fs::write(
+        &config,
+        format!(r#"{{"clock_corrections": {}}}"#, corrections),
+    )
+    .unwrap();
+
+    sandbox.import(&[
+        "--xmp-sidecar",
+        "--write-corrected-dates",
+        "--config",
+        config.to_str().unwrap(),
+        "--template",
+        "{date}_{time}",
+    ]);
+
+    sandbox
+}
+
+#[test]
+fn fixed_offset_is_applied_and_written_back() {
+    let sandbox = import_with_corrections(r#"[{"camera": "Test Camera", "offset": "+01:07"}]"#);
+    let output = &sandbox.output;
+
+    assert!(output.join("2023-01-04_11-22-00.jpg").exists());
+    assert!(
+        fs::read_to_string(output.join("2023-01-04_11-22-00.jpg.xmp"))
+            .unwrap()
+            .contains("<exif:DateTimeOriginal>2023-01-04T11:22:00</exif:DateTimeOriginal>")
+    );
+}
+
+#[test]
+fn drift_is_interpolated_between_references() {
+    // The clock gained 100 seconds over ten days; the photo was taken about
+    // 3.4 days in.
+    let sandbox = import_with_corrections(
+        r#"[
+            {"camera": "Other Camera", "offset": "+05:00"},
+            {"camera": "Test Camera", "references": [
+                {"camera_time": "2023:01:01 00:00:00", "actual_time": "2023:01:01 00:00:00"},
+                {"camera_time": "2023:01:11 00:00:00", "actual_time": "2023:01:11 00:01:40"}
+            ]}
+        ]"#,
+    );
+
+    assert!(sandbox.output.join("2023-01-04_10-15-34.jpg").exists());
+}
+
+#[test]
+fn corrects_and_writes_back_create_date_only_photos() {
+    let sandbox = Sandbox::new();
+    let config = sandbox.path().join("config.json");
+
+    // Has a CreateDate but no DateTimeOriginal.
+    sandbox.add_fixture("createdate.jpg", "IMG_0001.jpg");
+    fs::write(
+        &config,
+        r#"{"clock_corrections": [{"camera": "Fixture Camera One", "offset": "+01:07"}]}"#,
+    )
+    .unwrap();
+
+    let result = sandbox.run(&[
+        "import",
+        "--metadata-backend",
+        "native",
+        "--xmp-sidecar",
+        "--write-corrected-dates",
+        "--config",
+        config.to_str().unwrap(),
+        "--template",
+        "{date}_{time}",
+        "--output",
+        sandbox.output.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+
+    let sidecar = fs::read_to_string(sandbox.output.join("2023-01-04_11-22-00.jpg.xmp")).unwrap();
+    assert!(
+        sidecar.contains("<exif:DateTimeDigitized>2023-01-04T11:22:00</exif:DateTimeDigitized>"),
+        "{}",
+        sidecar
+    );
+    assert!(!sidecar.contains("DateTimeOriginal"), "{}", sidecar);
+}
+
+#[test]
+fn corrects_the_clock_before_placing_it_in_a_zone() {
+    let sandbox = Sandbox::new();
+    let config = sandbox.path().join("config.json");
+
+    sandbox.write("IMG_0001.jpg", "photo");
+    fs::write(
+        &config,
+        r#"{
+            "clock_corrections": [{"camera": "Test Camera", "offset": "+01:00"}],
+            "camera_offsets": {"Test Camera": "-05:00"}
+        }"#,
+    )
+    .unwrap();
+
+    sandbox.import(&[
+        "--config",
+        config.to_str().unwrap(),
+        "--display-tz",
+        "UTC",
+        "--template",
+        "{date}_{time}",
+    ]);
+
+    // 10:15 read, 11:15 corrected, at -05:00.
+    assert!(sandbox.output.join("2023-01-04_16-15-00.jpg").exists());
+}
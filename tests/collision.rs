@@ -0,0 +1,81 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{fixture, Sandbox};
+use serde_json::Value;
+use std::fs;
+
+/// The fixture photo with `extra` appended, which changes its checksum but
+/// not its metadata.
+fn variant(extra: &[u8]) -> Vec<u8> {
+    [fs::read(fixture("photo.jpg")).unwrap(), extra.to_vec()].concat()
+}
+
+#[test]
+fn tries_the_sub_second_time_and_then_a_number() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let output_arg = output.to_str().unwrap();
+    let input_arg = sandbox.input.to_str().unwrap();
+
+    // The native backend embeds XMP, so library files no longer hash like
+    // their sources; the catalog knows what each was imported from.
+    let import = || {
+        let result = sandbox.run(&[
+            "import",
+            "--metadata-backend",
+            "native",
+            "--output",
+            output_arg,
+            input_arg,
+        ]);
+        assert!(result.status.success(), "{:?}", result);
+        String::from_utf8(result.stdout).unwrap()
+    };
+
+    sandbox.add_fixture("photo.jpg", "a.jpg");
+    import();
+
+    // Two more shots of the same burst, taken in the same 1/100 second.
+    sandbox.write("b.jpg", variant(b"b"));
+    sandbox.write("c.jpg", variant(b"c"));
+    let stdout = import();
+    assert!(stdout.contains("Duplicates:  1"), "{}", stdout);
+
+    let camera = "timeline/2023-01-Jan/Fixture Camera One";
+    let names = sandbox.library(&["jpg"]).into_keys().collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            format!("{}/2023-01-04_10-15-00-25.jpg", camera),
+            format!("{}/2023-01-04_10-15-00.jpg", camera),
+            format!("{}/2023-01-04_10-15-00_1.jpg", camera),
+        ]
+    );
+
+    // Importing the burst again adds nothing.
+    let stdout = import();
+    assert!(stdout.contains("Duplicates:  3"), "{}", stdout);
+    assert_eq!(sandbox.library(&["jpg"]).len(), 3);
+}
+
+#[test]
+fn recognizes_identical_files_the_catalog_does_not_know() {
+    let sandbox = Sandbox::new();
+    let report = sandbox.path().join("report.json");
+    let camera = sandbox.output.join("timeline/2023-01-Jan/Test Camera");
+
+    fs::create_dir_all(&camera).unwrap();
+    fs::write(camera.join("2023-01-04_10-15-00.jpg"), "copied by hand").unwrap();
+    sandbox.write("a.jpg", "copied by hand");
+
+    sandbox.import(&["--xmp-sidecar", "--report", report.to_str().unwrap()]);
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report[0]["status"], "duplicate");
+    assert_eq!(
+        report[0]["of"],
+        "timeline/2023-01-Jan/Test Camera/2023-01-04_10-15-00.jpg"
+    );
+}
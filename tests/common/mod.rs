@@ -0,0 +1,126 @@
+//! Setup shared by the integration tests.
+
+// Each test binary compiles this module and uses only part of it.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A scratch directory with `input/` to import from, `output/` as the library
+/// and `tests/fake-exiftool.sh` installed as `exiftool` ahead of any real one
+/// on `PATH`.
+pub struct Sandbox {
+    dir: TempDir,
+    pub input: PathBuf,
+    pub output: PathBuf,
+    path: OsString,
+}
+
+impl Sandbox {
+    pub fn new() -> Sandbox {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input");
+        let bin = dir.path().join("bin");
+
+        fs::create_dir_all(&input).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        symlink(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fake-exiftool.sh"),
+            bin.join("exiftool"),
+        )
+        .unwrap();
+
+        let mut path = bin.into_os_string();
+        path.push(":");
+        path.push(std::env::var_os("PATH").unwrap_or_default());
+
+        Sandbox {
+            output: dir.path().join("output"),
+            input,
+            dir,
+            path,
+        }
+    }
+
+    /// The scratch directory, for files such as configs and reports.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Writes `contents` to `relative` under `input/`, creating directories.
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.input.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Copies the fixture `name` to `relative` under `input/`.
+    pub fn add_fixture(&self, name: &str, relative: &str) -> PathBuf {
+        self.write(relative, fs::read(fixture(name)).unwrap())
+    }
+
+    /// `photobot`, set up to find the fake exiftool.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_photobot"));
+        command.env("PATH", &self.path);
+        command
+    }
+
+    /// Runs `photobot` with `args`.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().unwrap()
+    }
+
+    /// Imports `input/` into `output/` through the fake exiftool, with `args`
+    /// added, and asserts that the import succeeded. Returns its stdout.
+    pub fn import(&self, args: &[&str]) -> String {
+        let result = self
+            .command()
+            .args(["import", "--metadata-backend", "exiftool", "--output"])
+            .arg(&self.output)
+            .args(args)
+            .arg(&self.input)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{:?}", result);
+        String::from_utf8(result.stdout).unwrap()
+    }
+
+    /// Every file in the library with one of `extensions`, by path relative
+    /// to the library root.
+    pub fn library(&self, extensions: &[&str]) -> BTreeMap<String, Vec<u8>> {
+        walkdir::WalkDir::new(&self.output)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.path()
+                    .extension()
+                    .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+            })
+            .map(|e| {
+                (
+                    e.path()
+                        .strip_prefix(&self.output)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned(),
+                    fs::read(e.path()).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A file from `tests/fixtures`. These are real images; see
+/// `make-fixtures.py` there for what they hold.
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
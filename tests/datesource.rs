@@ -0,0 +1,109 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use serde_json::Value;
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn falls_back_through_date_sources() {
+    let sandbox = Sandbox::new();
+    let plan = sandbox.path().join("plan.json");
+
+    sandbox.write("camera.jpg", "camera");
+    sandbox.write("nodate/IMG_20230104_101500.jpg", "android");
+    sandbox.write("nodate/Screenshot 2023-02-03 at 4.05.06 PM.png", "mac");
+    sandbox.write("nodate/IMG-20230105-WA0001.jpg", "whatsapp");
+    sandbox.write("nodate/2022-12-25 Christmas/scan.jpg", "scan");
+    fs::File::create(sandbox.write("nodate/mtime.jpg", ""))
+        .unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+        .unwrap();
+
+    let status = sandbox
+        .command()
+        .env("TZ", "UTC")
+        .args([
+            "test",
+            "--metadata-backend",
+            "exiftool",
+            "--template",
+            "{date}_{time}",
+            "--output",
+        ])
+        .arg(&sandbox.output)
+        .arg("--json")
+        .arg(&plan)
+        .arg(&sandbox.input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let plan: Vec<Value> = serde_json::from_str(&fs::read_to_string(&plan).unwrap()).unwrap();
+    let entry = |name: &str| {
+        plan.iter()
+            .find(|e| e["source"].as_str().unwrap().ends_with(name))
+            .unwrap_or_else(|| panic!("{} missing from plan", name))
+    };
+
+    for (name, stem, source) in [
+        ("camera.jpg", "2023-01-04_10-15-00", "exif"),
+        ("IMG_20230104_101500.jpg", "2023-01-04_10-15-00", "filename"),
+        (
+            "Screenshot 2023-02-03 at 4.05.06 PM.png",
+            "2023-02-03_16-05-06",
+            "filename",
+        ),
+        ("IMG-20230105-WA0001.jpg", "2023-01-05_00-00-00", "filename"),
+        ("scan.jpg", "2022-12-25_00-00-00", "folder"),
+        ("mtime.jpg", "2020-09-13_12-26-40", "mtime"),
+    ] {
+        let entry = entry(name);
+        assert_eq!(entry["date_source"], source, "{}", name);
+        // The Android photo shares its time with the camera photo, so one of
+        // them gets a numeric suffix.
+        let destination = entry["destination"].as_str().unwrap();
+        assert!(destination.starts_with(stem), "{}: {}", name, destination);
+    }
+}
+
+#[test]
+fn only_dates_files_from_the_sources_given() {
+    let sandbox = Sandbox::new();
+    let report = sandbox.path().join("report.json");
+
+    // Has an embedded date, which is not asked for.
+    sandbox.write("camera.jpg", "camera");
+    sandbox.write("IMG_20230201_080000.jpg", "android");
+    // Nothing but a modification time, which is not asked for either.
+    sandbox.write("nodate/undated.jpg", "undated");
+
+    sandbox.import(&[
+        "--report",
+        report.to_str().unwrap(),
+        "--date-sources",
+        "filename",
+    ]);
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let status = |name: &str| {
+        report
+            .iter()
+            .find(|e| e["source"].as_str().unwrap().ends_with(name))
+            .unwrap_or_else(|| panic!("{} missing from report", name))["status"]
+            .clone()
+    };
+    assert_eq!(status("camera.jpg"), "missing_date");
+    assert_eq!(status("IMG_20230201_080000.jpg"), "import");
+    assert_eq!(status("undated.jpg"), "missing_date");
+
+    // By default the modification time is the last resort.
+    let sandbox = Sandbox::new();
+    let report = sandbox.path().join("report.json");
+    sandbox.write("nodate/undated.jpg", "undated");
+    sandbox.import(&["--report", report.to_str().unwrap()]);
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report[0]["status"], "import");
+    assert_eq!(report[0]["date_source"], "mtime");
+}
@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn discovers_mixed_formats_case_insensitively() {
+    let sandbox = Sandbox::new();
+
+    let photos = [
+        "IMG_0001.JPG",
+        "IMG_0002.jpeg",
+        "iphone/IMG_0003.HEIC",
+        "iphone/IMG_0004.heif",
+        "screens/shot.png",
+        "scans/scan.TIFF",
+        "scans/scan2.tif",
+        "raw/a.dng",
+        "raw/b.CR2",
+        "raw/c.cr3",
+        "raw/d.NEF",
+        "raw/e.arw",
+        "raw/f.RAF",
+        "raw/g.orf",
+    ];
+    let ignored = ["notes.txt", "raw/a.dng.xmp", "clip.gif", "noextension"];
+
+    for file in photos.iter().chain(ignored.iter()) {
+        // Distinct contents keep the files from being treated as duplicates.
+        sandbox.write(file, file);
+    }
+
+    let plan_file = sandbox.path().join("plan.json");
+    let result = sandbox.run(&[
+        "test",
+        "--output",
+        sandbox.output.to_str().unwrap(),
+        "--json",
+        plan_file.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success());
+
+    let plan: Vec<serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(&plan_file).unwrap()).unwrap();
+
+    let found = plan
+        .iter()
+        .map(|entry| entry["source"].as_str().unwrap().to_string())
+        .collect::<BTreeSet<_>>();
+    let expected = photos
+        .iter()
+        .map(|p| sandbox.input.join(p).to_string_lossy().into_owned())
+        .collect::<BTreeSet<_>>();
+
+    assert_eq!(found, expected);
+    assert!(plan
+        .iter()
+        .all(|entry| entry["status"] == "import" || entry["status"] == "collision"));
+    assert!(!sandbox.output.exists());
+
+    // Nor does it touch an existing library and its catalog.
+    sandbox.import(&[]);
+    let before = snapshot(&sandbox.output);
+    let result = sandbox.run(&[
+        "test",
+        "--output",
+        sandbox.output.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+    assert_eq!(snapshot(&sandbox.output), before);
+}
+
+/// Every file under `dir` with its contents.
+fn snapshot(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| (e.path().to_path_buf(), fs::read(e.path()).unwrap()))
+        .collect()
+}
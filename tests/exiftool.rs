@@ -0,0 +1,31 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+#[test]
+fn shares_one_exiftool_process_and_restarts_after_crash() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path().join("exiftool.log");
+
+    for name in ["a.jpg", "b.jpg", "crash.jpg", "d.jpg"] {
+        sandbox.write(name, name);
+    }
+
+    let status = sandbox
+        .command()
+        .env("FAKE_EXIFTOOL_LOG", &log)
+        .args(["import", "--metadata-backend", "exiftool", "--output"])
+        .arg(&sandbox.output)
+        .arg(&sandbox.input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // One process for the whole import, plus one restart after the crash.
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 2);
+
+    assert_eq!(sandbox.library(&["jpg"]).len(), 4);
+}
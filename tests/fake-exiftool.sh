@@ -0,0 +1,47 @@
+#!/bin/sh
+# Minimal stand-in for `exiftool -stay_open True -@ -` used by the
+# integration tests. Every file reports the same capture date, except files
+# whose path contains "nodate", which have no metadata at all, and
+# "unsupported", which exiftool cannot read, and "quicktime", which only
+# has a QuickTime creation date, in UTC, an hour before. A file whose
+# name contains "crash" kills the process the first time it is read, to
+# exercise restarts, and writing to an ".orf" file fails. Each start is logged to $FAKE_EXIFTOOL_LOG if set.
+
+[ -n "$FAKE_EXIFTOOL_LOG" ] && echo start >> "$FAKE_EXIFTOOL_LOG"
+
+json=0
+file=""
+while IFS= read -r line; do
+    case "$line" in
+        -execute)
+            case "$file" in
+                *crash*)
+                    if [ ! -e "$file.crashed" ]; then
+                        touch "$file.crashed"
+                        exit 1
+                    fi
+                    ;;
+            esac
+            if [ "$json" = 1 ] && [ "${file#*unsupported}" != "$file" ]; then
+                printf '[{"SourceFile":"%s","ExifTool:Error":"Unknown file type"}]\n' "$file"
+            elif [ "$json" = 1 ] && [ "${file#*quicktime}" != "$file" ]; then
+                printf '[{"SourceFile":"%s","QuickTime:CreateDate":"2023:01:04 09:15:00","QuickTime:Make":"Test","QuickTime:Model":"Camera"}]\n' "$file"
+            elif [ "$json" = 1 ] && [ "${file#*nodate}" != "$file" ]; then
+                printf '[{"SourceFile":"%s"}]\n' "$file"
+            elif [ "$json" = 1 ]; then
+                printf '[{"SourceFile":"%s","EXIF:DateTimeOriginal":"2023:01:04 10:15:00","EXIF:Make":"Test","EXIF:Model":"Camera"}]\n' "$file"
+            elif [ "${file%.orf}" != "$file" ]; then
+                echo "    0 image files updated"
+            else
+                echo "    1 image files updated"
+            fi
+            echo "{ready}"
+            json=0
+            file=""
+            ;;
+        -json) json=1 ;;
+        -stay_open | -G | -overwrite_original | -*=*) ;;
+        False) exit 0 ;;
+        *) file="$line" ;;
+    esac
+done
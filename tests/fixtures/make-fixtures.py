@@ -0,0 +1,169 @@
+#!/usr/bin/env python3
+"""Writes the small image fixtures used by the tests.
+
+Each file is a real, decodable image carrying the same EXIF: a "Fixture
+Camera One" shot taken at 2023:01:04 10:15:00.25. They are checked in; rerun
+this script from tests/fixtures/ after changing it.
+"""
+
+import struct
+
+MAKE = "Fixture"
+MODEL = "Camera One"
+DATE = "2023:01:04 10:15:00"
+SUBSEC = "25"
+
+ASCII, SHORT, LONG = 2, 3, 4
+
+
+def ifd(entries, offset):
+    """Serializes a big-endian IFD starting at `offset`. `entries` are
+    (tag, type, value) with strings for ASCII and ints otherwise. Returns the
+    IFD followed by the values that don't fit in an entry."""
+    entries = sorted(entries)
+    data_offset = offset + 2 + 12 * len(entries) + 4
+    table = struct.pack(">H", len(entries))
+    data = b""
+
+    for tag, kind, value in entries:
+        if kind == ASCII:
+            raw = value.encode() + b"\0"
+            count = len(raw)
+        elif kind == SHORT:
+            raw = struct.pack(">H", value) + b"\0\0"
+            count = 1
+        else:
+            raw = struct.pack(">I", value)
+            count = 1
+
+        if len(raw) <= 4:
+            table += struct.pack(">HHI", tag, kind, count) + raw.ljust(4, b"\0")
+        else:
+            table += struct.pack(">HHII", tag, kind, count, data_offset + len(data))
+            data += raw
+            if len(data) % 2:
+                data += b"\0"
+
+    return table + struct.pack(">I", 0) + data
+
+
+def tiff(image_tags=(), image=b"", create_date_only=False):
+    """A TIFF structure with the camera in IFD0 and the dates in the EXIF
+    IFD. `image_tags` and `image` turn it into a standalone TIFF image."""
+    exif_entries = [(0x9004, ASCII, DATE), (0x9291, ASCII, SUBSEC)]
+    if not create_date_only:
+        exif_entries.append((0x9003, ASCII, DATE))
+
+    ifd0_entries = [
+        (0x010F, ASCII, MAKE),
+        (0x0110, ASCII, MODEL),
+        (0x8769, LONG, 0),
+    ] + list(image_tags)
+
+    # Lay out IFD0 once to learn its size, then point it at what follows.
+    size = len(ifd(ifd0_entries, 8))
+    exif_offset = 8 + size
+    exif_ifd = ifd(exif_entries, exif_offset)
+    image_offset = exif_offset + len(exif_ifd)
+
+    ifd0_entries = [
+        (tag, kind, exif_offset if tag == 0x8769 else image_offset if tag == 0x0111 else value)
+        for tag, kind, value in ifd0_entries
+    ]
+
+    return b"MM\0*" + struct.pack(">I", 8) + ifd(ifd0_entries, 8) + exif_ifd + image
+
+
+def segment(marker, payload):
+    return struct.pack(">BBH", 0xFF, marker, len(payload) + 2) + payload
+
+
+def jpeg(**kwargs):
+    """An 8x8 mid-grey baseline JPEG. With one-code Huffman tables the whole
+    scan is a zero DC difference followed by end-of-block."""
+    return b"".join(
+        [
+            b"\xff\xd8",
+            segment(0xE1, b"Exif\0\0" + tiff(**kwargs)),
+            segment(0xDB, b"\0" + b"\x01" * 64),
+            segment(0xC0, struct.pack(">BHHBBBB", 8, 8, 8, 1, 1, 0x11, 0)),
+            segment(0xC4, b"\x00" + b"\x01" + b"\0" * 15 + b"\x00"),
+            segment(0xC4, b"\x10" + b"\x01" + b"\0" * 15 + b"\x00"),
+            segment(0xDA, struct.pack(">BBBBBB", 1, 1, 0x00, 0, 63, 0)),
+            b"\x3f",
+            b"\xff\xd9",
+        ]
+    )
+
+
+def tiff_image():
+    """A 1x1 8-bit greyscale TIFF."""
+    return tiff(
+        image_tags=[
+            (0x0100, SHORT, 1),
+            (0x0101, SHORT, 1),
+            (0x0102, SHORT, 8),
+            (0x0103, SHORT, 1),
+            (0x0106, SHORT, 1),
+            (0x0111, LONG, 0),
+            (0x0115, SHORT, 1),
+            (0x0116, SHORT, 1),
+            (0x0117, LONG, 1),
+        ],
+        image=b"\x80",
+    )
+
+
+def box(kind, payload):
+    return struct.pack(">I", 8 + len(payload)) + kind + payload
+
+
+def full_box(kind, version, payload):
+    return box(kind, struct.pack(">I", version << 24) + payload)
+
+
+def heic():
+    """A HEIF container with an `hvc1` image item and an `Exif` item
+    describing it. The image item holds placeholder bytes rather than coded
+    HEVC, which metadata readers never look at."""
+    image = b"\0" * 16
+    exif = struct.pack(">I", 0) + tiff()
+
+    def meta(mdat_offset):
+        return full_box(
+            b"meta",
+            0,
+            full_box(b"hdlr", 0, struct.pack(">I", 0) + b"pict" + b"\0" * 13)
+            + full_box(b"pitm", 0, struct.pack(">H", 1))
+            + full_box(
+                b"iloc",
+                0,
+                struct.pack(">HH", 0x4400, 2)
+                + struct.pack(">HHHII", 1, 0, 1, mdat_offset + 8, len(image))
+                + struct.pack(">HHHII", 2, 0, 1, mdat_offset + 8 + len(image), len(exif)),
+            )
+            + full_box(
+                b"iinf",
+                0,
+                struct.pack(">H", 2)
+                + full_box(b"infe", 2, struct.pack(">HH", 1, 0) + b"hvc1" + b"\0")
+                + full_box(b"infe", 2, struct.pack(">HH", 2, 0) + b"Exif" + b"\0"),
+            )
+            + full_box(b"iref", 0, box(b"cdsc", struct.pack(">HHH", 2, 1, 1))),
+        )
+
+    ftyp = box(b"ftyp", b"heic" + struct.pack(">I", 0) + b"mif1heic")
+    mdat_offset = len(ftyp) + len(meta(0))
+
+    return ftyp + meta(mdat_offset) + box(b"mdat", image + exif)
+
+
+if __name__ == "__main__":
+    for name, data in [
+        ("photo.jpg", jpeg()),
+        ("createdate.jpg", jpeg(create_date_only=True)),
+        ("photo.tif", tiff_image()),
+        ("photo.heic", heic()),
+    ]:
+        with open(name, "wb") as f:
+            f.write(data)
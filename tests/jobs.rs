@@ -0,0 +1,34 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::collections::BTreeMap;
+
+/// Imports the same burst of same-second photos and returns the library as a
+/// map of relative path to contents.
+fn import_with_jobs(jobs: &str) -> BTreeMap<String, Vec<u8>> {
+    let sandbox = Sandbox::new();
+
+    for i in 0..12 {
+        let name = format!("IMG_{:04}.jpg", 12 - i);
+        sandbox.write(&name, &name);
+    }
+    sandbox.write("nested/IMG_0003.jpg", "IMG_0003.jpg");
+    sandbox.write("nested/IMG_9999.jpg", "IMG_9999.jpg");
+
+    sandbox.import(&["--jobs", jobs]);
+    sandbox.library(&["jpg"])
+}
+
+#[test]
+fn naming_does_not_depend_on_job_count() {
+    let sequential = import_with_jobs("1");
+
+    // Thirteen distinct photos; the nested copy of IMG_0003 is a duplicate.
+    assert_eq!(sequential.len(), 13);
+
+    for _ in 0..3 {
+        assert_eq!(import_with_jobs("8"), sequential);
+    }
+}
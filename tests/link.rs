@@ -0,0 +1,47 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Imports a single photo with `--link <mode>` and returns the source and the
+/// library file.
+fn import_linked(sandbox: &Sandbox, mode: &str) -> (PathBuf, PathBuf) {
+    let source = sandbox.write("IMG_0001.jpg", "photo");
+
+    sandbox.import(&["--link", mode]);
+
+    let imported = sandbox.library(&["jpg"]).into_keys().next().unwrap();
+
+    (source, sandbox.output.join(imported))
+}
+
+fn sidecar(path: &Path) -> String {
+    fs::read_to_string(format!("{}.xmp", path.display())).unwrap()
+}
+
+#[test]
+fn hard_link_shares_inode_and_writes_sidecar() {
+    let sandbox = Sandbox::new();
+    let (source, imported) = import_linked(&sandbox, "hard");
+
+    assert_eq!(
+        fs::metadata(&source).unwrap().ino(),
+        fs::metadata(&imported).unwrap().ino()
+    );
+    assert_eq!(fs::read_to_string(&source).unwrap(), "photo");
+    assert!(sidecar(&imported).contains("IMG_0001.jpg"));
+}
+
+#[test]
+fn reflink_falls_back_to_copy_and_writes_sidecar() {
+    let sandbox = Sandbox::new();
+    let (source, imported) = import_linked(&sandbox, "reflink");
+
+    assert_eq!(fs::read_to_string(&source).unwrap(), "photo");
+    assert_eq!(fs::read_to_string(&imported).unwrap(), "photo");
+    assert!(sidecar(&imported).contains("IMG_0001.jpg"));
+}
@@ -0,0 +1,26 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+
+#[test]
+fn move_removes_imported_sources_only() {
+    let sandbox = Sandbox::new();
+    let a = sandbox.write("a.jpg", "a");
+    let b = sandbox.write("b.jpg", "b");
+    let c = sandbox.write("c.jpg", "a");
+    let notes = sandbox.write("notes.txt", "not a photo");
+
+    sandbox.import(&["--move"]);
+
+    // c.jpg duplicates a.jpg, so it was never copied and must stay behind.
+    assert!(!a.exists());
+    assert!(!b.exists());
+    assert!(c.exists());
+    assert!(notes.exists());
+
+    let mut imported = sandbox.library(&["jpg"]).into_values().collect::<Vec<_>>();
+    imported.sort();
+    assert_eq!(imported, [b"a", b"b"]);
+}
@@ -0,0 +1,81 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn takes_back_a_group_when_one_photo_fails() {
+    let sandbox = Sandbox::new();
+    let report = sandbox.path().join("report.json");
+
+    // The JPEG is imported first; tagging the RAW then fails.
+    let jpg = sandbox.write("IMG_0001.jpg", "jpeg");
+    let orf = sandbox.write("IMG_0001.orf", "raw");
+    sandbox.write("IMG_0002.jpg", "other");
+
+    sandbox.import(&["--move", "--report", report.to_str().unwrap()]);
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let status = |name: &str| {
+        report
+            .iter()
+            .find(|e| e["source"].as_str().unwrap().ends_with(name))
+            .unwrap_or_else(|| panic!("{} missing from report", name))["status"]
+            .clone()
+    };
+    assert_eq!(status("IMG_0001.jpg"), "transfer_failed");
+    assert_eq!(status("IMG_0001.orf"), "transfer_failed");
+    assert_ne!(status("IMG_0002.jpg"), "transfer_failed");
+
+    // Both are back where they were, and only the unrelated photo is in the
+    // library or the catalog.
+    assert_eq!(fs::read(&jpg).unwrap(), b"jpeg");
+    assert_eq!(fs::read(&orf).unwrap(), b"raw");
+    assert_eq!(
+        sandbox
+            .library(&["jpg", "orf"])
+            .into_values()
+            .collect::<Vec<_>>(),
+        [b"other"]
+    );
+    let catalog = rusqlite::Connection::open(sandbox.output.join("catalog.db")).unwrap();
+    let entries: i64 = catalog
+        .query_row("SELECT count(*) FROM photos", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(entries, 1);
+}
+
+#[test]
+fn pairs_keep_their_own_layout_directory() {
+    let sandbox = Sandbox::new();
+    sandbox.write("IMG_0001.jpg", "live photo");
+    sandbox.write("IMG_0001.mov", "live video");
+
+    sandbox.import(&["--videos", "separate"]);
+
+    let stem = "timeline/2023-01-Jan/Test Camera/2023-01-04_10-15-00";
+    let library = sandbox.library(&["jpg", "mov"]);
+    assert_eq!(library[&format!("{}.jpg", stem)], b"live photo");
+    assert_eq!(library[&format!("videos/{}.mov", stem)], b"live video");
+
+    // A pair renamed after a collision stays apart the same way.
+    let report = sandbox.path().join("report.json");
+    sandbox.write("IMG_0002.jpg", "second photo");
+    sandbox.write("IMG_0002.mov", "second video");
+    sandbox.import(&["--videos", "separate", "--report", report.to_str().unwrap()]);
+
+    let library = sandbox.library(&["jpg", "mov"]);
+    assert_eq!(library[&format!("{}_1.jpg", stem)], b"second photo");
+    assert_eq!(library[&format!("videos/{}_1.mov", stem)], b"second video");
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let video = report
+        .iter()
+        .find(|e| e["source"].as_str().unwrap().ends_with("IMG_0002.mov"))
+        .unwrap();
+    assert_eq!(video["status"], "collision");
+    assert_eq!(video["existing"], format!("videos/{}.mov", stem));
+}
@@ -0,0 +1,71 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use serde_json::Value;
+use std::process::Output;
+
+fn import(sandbox: &Sandbox, output: &str, args: &[&str]) -> Output {
+    let result = sandbox
+        .command()
+        .args(["import", "--metadata-backend", "exiftool", "--output"])
+        .arg(sandbox.path().join(output))
+        .args(args)
+        .arg(&sandbox.input)
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{:?}", result);
+    result
+}
+
+#[test]
+fn prints_plain_progress_lines_to_stderr_when_piped() {
+    let sandbox = Sandbox::new();
+    sandbox.write("a.jpg", "first");
+    sandbox.write("b.jpg", "second");
+
+    let result = import(&sandbox, "library", &[]);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("Scanning: 2/2 files, 11 B/11 B"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Importing: 2/2 files, 11 B/11 B"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains('\x1b'), "{}", stderr);
+
+    // Stdout holds only the summary.
+    assert!(!stdout.contains("Scanning:"), "{}", stdout);
+    assert!(stdout.contains("Found:       2"), "{}", stdout);
+
+    let result = import(&sandbox, "quiet", &["-q"]);
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(!stderr.contains("Scanning:"), "{}", stderr);
+}
+
+#[test]
+fn logs_progress_as_events_with_json_logs() {
+    let sandbox = Sandbox::new();
+    sandbox.write("a.jpg", "first");
+
+    let result = import(&sandbox, "library", &["--log-format", "json"]);
+    let stderr = String::from_utf8(result.stderr).unwrap();
+
+    // Every line on stderr is a log event.
+    let events = stderr
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap_or_else(|_| panic!("{}", line)))
+        .collect::<Vec<_>>();
+    let importing = events
+        .iter()
+        .find(|e| e["fields"]["phase"] == "Importing")
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert_eq!(importing["fields"]["files"], 1);
+    assert_eq!(importing["fields"]["total_bytes"], 5);
+}
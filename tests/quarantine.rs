@@ -0,0 +1,86 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn quarantines_files_that_cannot_be_imported() {
+    let sandbox = Sandbox::new();
+    sandbox.write("good.jpg", "good");
+    sandbox.write("nodate/scans/scan.jpg", "scan");
+    sandbox.write("unsupported.tif", "garbage");
+
+    sandbox.import(&["--date-sources", "exif"]);
+
+    let unsorted = sandbox.output.join("unsorted");
+    let reason = |name: &str| -> Value {
+        serde_json::from_str(
+            &fs::read_to_string(unsorted.join(format!("{}.reason.json", name))).unwrap(),
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        fs::read_to_string(unsorted.join("nodate/scans/scan.jpg")).unwrap(),
+        "scan"
+    );
+    assert_eq!(reason("nodate/scans/scan.jpg")["reason"], "missing_date");
+
+    assert_eq!(
+        fs::read_to_string(unsorted.join("unsupported.tif")).unwrap(),
+        "garbage"
+    );
+    assert_eq!(reason("unsupported.tif")["reason"], "unsupported_format");
+    assert!(sandbox.input.join("unsupported.tif").exists());
+
+    assert!(!unsorted.join("good.jpg").exists());
+}
+
+#[test]
+fn keeps_files_quarantined_under_the_same_path() {
+    let sandbox = Sandbox::new();
+    let first = sandbox.path().join("first");
+    let second = sandbox.path().join("second");
+    for (dir, contents) in [(&first, "first"), (&second, "second")] {
+        fs::create_dir_all(dir.join("nodate")).unwrap();
+        fs::write(dir.join("nodate/scan.jpg"), contents).unwrap();
+    }
+
+    let import = || {
+        let result = sandbox
+            .command()
+            .args([
+                "import",
+                "--metadata-backend",
+                "exiftool",
+                "--date-sources",
+                "exif",
+                "--output",
+            ])
+            .arg(&sandbox.output)
+            .arg(&first)
+            .arg(&second)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{:?}", result);
+    };
+
+    import();
+    let unsorted = sandbox.output.join("unsorted/nodate");
+    assert_eq!(
+        fs::read_to_string(unsorted.join("scan.jpg")).unwrap(),
+        "first"
+    );
+    assert_eq!(
+        fs::read_to_string(unsorted.join("scan_1.jpg")).unwrap(),
+        "second"
+    );
+    assert!(unsorted.join("scan_1.jpg.reason.json").exists());
+
+    // Quarantining the same files again adds no copies.
+    import();
+    assert_eq!(fs::read_dir(&unsorted).unwrap().count(), 4);
+}
@@ -0,0 +1,131 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn rebuilds_the_catalog_from_the_library() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let report = sandbox.path().join("report.json");
+
+    sandbox.write("a.jpg", "first");
+    sandbox.write("b.jpg", "second");
+
+    // Sidecars carry the original file names, which the rebuild reads back.
+    sandbox.import(&["--xmp-sidecar"]);
+
+    let camera = output.join("timeline/2023-01-Jan/Test Camera");
+    fs::remove_file(output.join("catalog.db")).unwrap();
+    fs::create_dir_all(output.join("misc")).unwrap();
+    fs::rename(
+        camera.join("2023-01-04_10-15-00_1.jpg"),
+        output.join("misc/odd.jpg"),
+    )
+    .unwrap();
+    fs::copy(
+        camera.join("2023-01-04_10-15-00.jpg"),
+        output.join("misc/z-copy.jpg"),
+    )
+    .unwrap();
+    fs::create_dir_all(output.join("unsorted")).unwrap();
+    fs::write(output.join("unsorted/skipped.jpg"), "skipped").unwrap();
+
+    let result = sandbox.run(&[
+        "rebuild-index",
+        output.to_str().unwrap(),
+        "--metadata-backend",
+        "exiftool",
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            "misc/odd.jpg (expected at timeline/2023-01-Jan/Test Camera/2023-01-04_10-15-00.jpg)"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("misc/z-copy.jpg (duplicate of timeline/"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("skipped"), "{}", stdout);
+    assert!(stdout.contains("Indexed 2 files"), "{}", stdout);
+
+    let catalog = Connection::open(output.join("catalog.db")).unwrap();
+    let count: i64 = catalog
+        .query_row("SELECT count(*) FROM photos", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+
+    // The rebuilt catalog is used to skip both files on the next import.
+    sandbox.import(&["--report", report.to_str().unwrap()]);
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report[0]["status"], "duplicate");
+    assert_eq!(report[1]["status"], "duplicate");
+    assert_eq!(report[1]["of"], "misc/odd.jpg");
+}
+
+#[test]
+fn keeps_what_files_were_imported_from() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let output_arg = output.to_str().unwrap();
+    let report = sandbox.path().join("report.json");
+
+    sandbox.add_fixture("photo.jpg", "a.jpg");
+    sandbox.add_fixture("createdate.jpg", "b.jpg");
+
+    // The native backend embeds XMP, so the library files differ from their
+    // sources.
+    let native = |args: &[&str]| {
+        let result = sandbox.run(&[args, &["--metadata-backend", "native"]].concat());
+        assert!(result.status.success(), "{:?}", result);
+        String::from_utf8(result.stdout).unwrap()
+    };
+    native(&[
+        "import",
+        "--output",
+        output_arg,
+        sandbox.input.to_str().unwrap(),
+    ]);
+
+    // One file stays put, the other is moved by hand.
+    let camera = output.join("timeline/2023-01-Jan/Fixture Camera One");
+    let moved = fs::read_dir(&camera)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension().is_some_and(|e| e == "jpg"))
+        .unwrap();
+    fs::create_dir_all(output.join("misc")).unwrap();
+    fs::rename(&moved, output.join("misc/moved.jpg")).unwrap();
+
+    let stdout = native(&["rebuild-index", output_arg]);
+    assert!(
+        stdout.contains("Dropped 0 catalog entries no file matched."),
+        "{}",
+        stdout
+    );
+
+    native(&[
+        "import",
+        "--output",
+        output_arg,
+        "--report",
+        report.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report.len(), 2);
+    assert!(
+        report.iter().all(|entry| entry["status"] == "duplicate"),
+        "{:?}",
+        report
+    );
+}
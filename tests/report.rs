@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use serde_json::Value;
+use std::fs;
+
+#[test]
+fn reports_each_file_and_summarizes_the_import() {
+    let sandbox = Sandbox::new();
+    let report = sandbox.path().join("report.json");
+
+    // Every file the fake exiftool knows shares one capture time, so b.jpg
+    // collides with a.jpg and c.jpg duplicates it.
+    sandbox.write("a.jpg", "first");
+    sandbox.write("b.jpg", "second!");
+    sandbox.write("c.jpg", "first");
+    sandbox.write("nodate.jpg", "undated");
+    sandbox.write("unsupported.tif", "garbage");
+
+    let stdout = sandbox.import(&[
+        "--date-sources",
+        "exif",
+        "--report",
+        report.to_str().unwrap(),
+    ]);
+
+    assert!(stdout.contains("Found:       5"), "{}", stdout);
+    assert!(
+        stdout.contains("Imported:    2 (1 renamed after a name collision)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Duplicates:  1"), "{}", stdout);
+    assert!(
+        stdout.contains("Failed:      2 (1 missing dates, 1 unsupported formats"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Copied:      12 B"), "{}", stdout);
+
+    let report: Vec<Value> = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let entry = |name: &str| {
+        report
+            .iter()
+            .find(|e| e["source"].as_str().unwrap().ends_with(name))
+            .unwrap_or_else(|| panic!("{} missing from report", name))
+    };
+
+    assert_eq!(report.len(), 5);
+
+    let a = entry("a.jpg");
+    assert_eq!(a["status"], "import");
+    assert_eq!(a["date_source"], "exif");
+    assert_eq!(a["bytes_copied"], 5);
+    assert_eq!(a["checksum"]["size"], 5);
+    assert!(sandbox
+        .output
+        .join(a["destination"].as_str().unwrap())
+        .exists());
+
+    assert_eq!(entry("b.jpg")["status"], "collision");
+    assert_eq!(entry("c.jpg")["status"], "duplicate");
+    assert_eq!(entry("c.jpg")["of"], a["destination"]);
+
+    let nodate = entry("nodate.jpg");
+    assert_eq!(nodate["status"], "missing_date");
+    assert!(nodate["quarantined_to"]
+        .as_str()
+        .unwrap()
+        .ends_with("unsorted/nodate.jpg"));
+
+    assert_eq!(entry("unsupported.tif")["status"], "unsupported_format");
+}
@@ -0,0 +1,94 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::fs;
+use std::path::Path;
+
+const LIGHTROOM_SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+   exif:DateTimeOriginal="2019-07-14T08:30:00.50+02:00"
+   tiff:Model="Retouched"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+#[test]
+fn respects_existing_sidecars_and_leaves_image_bytes_alone() {
+    let sandbox = Sandbox::new();
+    sandbox.write("IMG_0001.jpg", "edited");
+    sandbox.write("IMG_0001.xmp", LIGHTROOM_SIDECAR);
+    sandbox.write("IMG_0002.jpg", "untouched");
+
+    sandbox.import(&["--xmp-sidecar", "--template", "{date}_{time}_{model}"]);
+
+    let output = &sandbox.output;
+    let edited = output.join("2019-07-14_08-30-00_Retouched.jpg");
+    assert_eq!(fs::read_to_string(&edited).unwrap(), "edited");
+    assert!(
+        fs::read_to_string(output.join("2019-07-14_08-30-00_Retouched.jpg.xmp"))
+            .unwrap()
+            .contains("IMG_0001.jpg")
+    );
+
+    let untouched = output.join("2023-01-04_10-15-00_Camera.jpg");
+    assert_eq!(fs::read_to_string(&untouched).unwrap(), "untouched");
+    assert!(output.join("2023-01-04_10-15-00_Camera.jpg.xmp").exists());
+}
+
+#[test]
+fn falls_back_to_sidecars_for_files_no_backend_can_write() {
+    let sandbox = Sandbox::new();
+    sandbox.add_fixture("photo.heic", "IMG_0001.heic");
+    sandbox.add_fixture("photo.tif", "scans/IMG_0002.tif");
+
+    let check = |output: &Path| {
+        for (name, fixture) in [
+            ("IMG_0001.heic", "photo.heic"),
+            ("IMG_0002.tif", "photo.tif"),
+        ] {
+            let imported = walkdir::WalkDir::new(output)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .find(|e| e.path().extension() == Path::new(fixture).extension())
+                .unwrap_or_else(|| panic!("{} not imported", name))
+                .into_path();
+            assert_eq!(
+                fs::read(&imported).unwrap(),
+                fs::read(common::fixture(fixture)).unwrap()
+            );
+
+            let mut sidecar = imported.into_os_string();
+            sidecar.push(".xmp");
+            assert!(fs::read_to_string(&sidecar).unwrap().contains(name));
+        }
+    };
+
+    let result = sandbox.run(&[
+        "import",
+        "--metadata-backend",
+        "native",
+        "--output",
+        sandbox.output.to_str().unwrap(),
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+    check(&sandbox.output);
+
+    // The default backend needs no exiftool for them either.
+    let without_exiftool = sandbox.path().join("without-exiftool");
+    let result = sandbox
+        .command()
+        .env("PATH", sandbox.path().join("empty"))
+        .args(["import", "--output"])
+        .arg(&without_exiftool)
+        .arg(&sandbox.input)
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{:?}", result);
+    check(&without_exiftool);
+}
@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use std::fs;
+
+fn sidecar(date: &str) -> String {
+    format!(
+        r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+   exif:DateTimeOriginal="{}"
+   tiff:Model="Phone"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#,
+        date
+    )
+}
+
+#[test]
+fn normalizes_offsets_to_display_zone() {
+    let sandbox = Sandbox::new();
+    let config = sandbox.path().join("config.json");
+
+    // Taken in Tokyo and Berlin half an hour apart.
+    sandbox.write("tokyo.jpg", "tokyo");
+    sandbox.write("tokyo.xmp", sidecar("2023-06-01T18:00:00+09:00"));
+    sandbox.write("berlin.jpg", "berlin");
+    sandbox.write("berlin.xmp", sidecar("2023-06-01T11:30:00+02:00"));
+    // No offset recorded; the config says this camera runs on New York time.
+    sandbox.write("camera.jpg", "camera");
+    fs::write(&config, r#"{"camera_offsets": {"Test Camera": "-05:00"}}"#).unwrap();
+
+    sandbox.import(&[
+        "--xmp-sidecar",
+        "--display-tz",
+        "UTC",
+        "--config",
+        config.to_str().unwrap(),
+        "--template",
+        "{date}_{time}_{orig_stem}",
+    ]);
+
+    for name in [
+        "2023-06-01_09-00-00_tokyo.jpg",
+        "2023-06-01_09-30-00_berlin.jpg",
+        "2023-01-04_15-15-00_camera.jpg",
+    ] {
+        assert!(
+            sandbox.output.join(name).exists(),
+            "{} was not imported",
+            name
+        );
+    }
+}
+
+#[test]
+fn dates_videos_from_utc_in_the_display_zone_or_camera_zone() {
+    // The host's own zone plays no part once either is given.
+    let import = |args: &[&str]| {
+        let sandbox = Sandbox::new();
+        sandbox.write("clip_quicktime.mov", "video");
+        let result = sandbox
+            .command()
+            .env("TZ", "Asia/Tokyo")
+            .args(["import", "--metadata-backend", "exiftool", "--output"])
+            .arg(&sandbox.output)
+            .args(["--template", "{date}_{time}"])
+            .args(args)
+            .arg(&sandbox.input)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{:?}", result);
+        sandbox.library(&["mov"]).into_keys().collect::<Vec<_>>()
+    };
+
+    // Recorded at 09:15 UTC.
+    assert_eq!(
+        import(&["--display-tz", "+02:00"]),
+        ["2023-01-04_11-15-00.mov"]
+    );
+    assert_eq!(import(&["--camera-tz=-05:00"]), ["2023-01-04_04-15-00.mov"]);
+}
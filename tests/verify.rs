@@ -0,0 +1,146 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Sandbox;
+use rusqlite::Connection;
+use std::fs;
+use std::process::Output;
+
+#[test]
+fn finds_corrupt_missing_and_unindexed_files() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+
+    sandbox.write("a.jpg", "first");
+    sandbox.write("b.jpg", "second");
+    sandbox.write("c.jpg", "third");
+
+    let photobot = |args: &[&str]| -> Output {
+        sandbox.run(&[args, &["--metadata-backend", "exiftool"]].concat())
+    };
+
+    let output_arg = output.to_str().unwrap();
+
+    // Without a catalog there is nothing to verify against.
+    let result = photobot(&["verify", output_arg]);
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("rebuild-index"));
+
+    sandbox.import(&[]);
+
+    let result = photobot(&["verify", output_arg]);
+    assert!(result.status.success(), "{:?}", result);
+    assert!(String::from_utf8_lossy(&result.stdout).contains(
+        "Verified 3 files: 0 corrupt, 0 missing, 0 errors. 0 files are not in the catalog."
+    ));
+
+    let camera = output.join("timeline/2023-01-Jan/Test Camera");
+    fs::write(camera.join("2023-01-04_10-15-00.jpg"), "flipped").unwrap();
+    fs::remove_file(camera.join("2023-01-04_10-15-00_1.jpg")).unwrap();
+    fs::write(camera.join("stray.jpg"), "stray").unwrap();
+
+    let old = "2000-01-01T00:00:00Z";
+    let catalog = Connection::open(output.join("catalog.db")).unwrap();
+    catalog
+        .execute("UPDATE photos SET last_verified = ?1", [old])
+        .unwrap();
+
+    let result = photobot(&["verify", output_arg]);
+    assert!(!result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains("Test Camera/2023-01-04_10-15-00.jpg (corrupt: checksum "),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Test Camera/2023-01-04_10-15-00_1.jpg (missing)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Test Camera/stray.jpg (not in the catalog)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(
+            "Verified 3 files: 1 corrupt, 1 missing, 0 errors. 1 files are not in the catalog."
+        ),
+        "{}",
+        stdout
+    );
+
+    // Only the intact file counts as verified.
+    let stale: i64 = catalog
+        .query_row(
+            "SELECT count(*) FROM photos WHERE last_verified = ?1",
+            [old],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(stale, 2);
+    drop(catalog);
+
+    // Files verified recently are skipped, and a sample is capped.
+    let result = photobot(&["verify", "--not-verified-for", "1", output_arg]);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains("Verified 2 files: 1 corrupt, 1 missing"),
+        "{}",
+        stdout
+    );
+
+    let result = photobot(&["verify", "--sample", "2", output_arg]);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.contains("Verified 2 files:"), "{}", stdout);
+}
+
+#[test]
+fn verifies_files_with_tags_written_into_them() {
+    let sandbox = Sandbox::new();
+    let output = &sandbox.output;
+    let output_arg = output.to_str().unwrap();
+    sandbox.add_fixture("photo.jpg", "photo.jpg");
+
+    // Without `--xmp-sidecar` the copy gets XMP embedded, so its bytes are
+    // not the source's.
+    let native = |args: &[&str]| -> Output {
+        sandbox.run(&[args, &["--metadata-backend", "native"]].concat())
+    };
+    let result = native(&[
+        "import",
+        "--output",
+        output_arg,
+        sandbox.input.to_str().unwrap(),
+    ]);
+    assert!(result.status.success(), "{:?}", result);
+
+    let result = native(&["verify", output_arg]);
+    assert!(result.status.success(), "{:?}", result);
+    assert!(
+        String::from_utf8_lossy(&result.stdout).contains("Verified 1 files: 0 corrupt"),
+        "{:?}",
+        result
+    );
+
+    // Catalogs from before library checksums were kept get them recorded.
+    let catalog = Connection::open(output.join("catalog.db")).unwrap();
+    catalog
+        .execute("UPDATE photos SET file_hash = NULL, file_size = NULL", [])
+        .unwrap();
+
+    let result = native(&["verify", output_arg]);
+    assert!(result.status.success(), "{:?}", result);
+    assert!(
+        String::from_utf8_lossy(&result.stdout)
+            .contains("1 files had no checksum in the catalog; theirs are recorded now."),
+        "{:?}",
+        result
+    );
+    let recorded: Option<String> = catalog
+        .query_row("SELECT file_hash FROM photos", [], |row| row.get(0))
+        .unwrap();
+    assert!(recorded.is_some());
+}